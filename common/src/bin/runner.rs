@@ -1,12 +1,24 @@
 // PORT_START: u16 = 9_000;
 // PORT_END: u16 = 9_100;
 
-use common::{InteropTest, UNIMPLEMENTED_RETURN_VAL};
-use std::collections::BTreeMap;
+use common::{
+    CipherSuite, DataVolume, ExitCode, InteropTest, KeyExchangeGroup, NegotiatedParams,
+    ScenarioMetrics, TestParameters, TlsAlert, TlsVersion, EXPECTED_FAILURE_LOG,
+    SMALL_TCP_PACKET_BYTES,
+};
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::Ipv4Addr;
+#[cfg(target_os = "linux")]
+use std::os::unix::process::ExitStatusExt;
+use std::process::ExitStatus;
+use std::sync::Mutex;
 use std::time::Instant;
-use std::{process::Stdio, sync::Arc, thread, time::Duration};
+use std::{io, process::Stdio, sync::Arc, thread, time::Duration};
 use tokio::{
-    process::Command,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    process::{ChildStdout, Command},
     sync::{mpsc::unbounded_channel, Semaphore},
     time::{sleep, timeout},
 };
@@ -21,15 +33,48 @@ const PORT_RANGE_START: u16 = 9_001;
 /// Long pole as of 2024-04-19 was Rustls/OpenSSL large data download test
 const TEST_TIMEOUT: Duration = Duration::from_secs(7 * 60);
 
-const ENABLED_TESTS: [InteropTest; 5] = [
+const ENABLED_TESTS: [InteropTest; 26] = [
     InteropTest::Handshake,
     InteropTest::Greeting,
     InteropTest::MTLSRequestResponse,
     InteropTest::LargeDataDownload,
     InteropTest::LargeDataDownloadWithFrequentKeyUpdates,
-    //InteropTest::SessionResumption,
+    InteropTest::LargeDataUpload,
+    InteropTest::LargeDataUploadWithFrequentKeyUpdates,
+    InteropTest::HelloRetryRequest,
+    InteropTest::SmallTcpPacket,
+    InteropTest::KeyUpdateRequest,
+    InteropTest::KeyUpdateFlood,
+    InteropTest::AlpnMatch,
+    InteropTest::AlpnServerPreference,
+    InteropTest::AlpnNoOverlap,
+    InteropTest::AlpnServerUnused,
+    InteropTest::SniSelection,
+    InteropTest::SniAbsent,
+    InteropTest::UntrustedServerCa,
+    InteropTest::HostnameMismatch,
+    InteropTest::ExpiredServerCert,
+    InteropTest::MTLSMissingClientCert,
+    InteropTest::MTLSUntrustedClientCert,
+    InteropTest::SessionResumption,
+    InteropTest::SessionIdResumption,
+    InteropTest::RenegotiationRefusal,
+    InteropTest::PqHandshake,
 ];
 
+/// The number of cores that a scenario is assumed to keep busy until a
+/// scenario of the same test case has been measured. The large data scenarios
+/// are capable of saturating 1 core for the client and 1 for the server.
+const DEFAULT_SCENARIO_CORES: u32 = 2;
+
+/// The metrics of every connection are collected in this file, with one row
+/// per connection of each peer.
+const METRICS_PATH: &str = "interop_logs/metrics.csv";
+
+/// In addition to the default algorithms, these tests are executed with each
+/// TLS 1.3 cipher suite and each key exchange group pinned in turn.
+const ALGORITHM_MATRIX_TESTS: [InteropTest; 1] = [InteropTest::Greeting];
+
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
 enum Client {
     S2nTls,
@@ -68,7 +113,7 @@ impl Client {
 
     // lifetimes are used to indicate that the returned `&mut Command` has the same
     // lifetime as the input `&mut Command`
-    fn configure<'a, 'b>(&'a self, command: &'b mut Command) -> &'b mut Command {
+    fn configure<'b>(&self, command: &'b mut Command) -> &'b mut Command {
         match self {
             Client::Java => command
                 // configure the class path (-cp)
@@ -80,6 +125,23 @@ impl Client {
             _ => command,
         }
     }
+
+    /// The alerts that the client reports when its connection fails
+    fn alert_reporting(&self) -> AlertReporting {
+        match self {
+            Client::Rustls => AlertReporting::ALL,
+            // s2n-tls only exposes received alerts through the connection, and
+            // the JDK and crypto/tls only expose them in their errors
+            Client::S2nTls | Client::Java | Client::Go => AlertReporting::RECEIVED,
+        }
+    }
+
+    /// The rust clients fragment their own transport in the small tcp packet
+    /// scenario. Other clients don't offer control over their socket writes, so
+    /// their traffic is routed through a local fragmenting proxy.
+    fn requires_fragmenting_proxy(&self, test: InteropTest) -> bool {
+        test == InteropTest::SmallTcpPacket && matches!(self, Client::Java | Client::Go)
+    }
 }
 
 impl Server {
@@ -97,6 +159,34 @@ impl Server {
             ),
         }
     }
+
+    /// The alerts that the server reports when its connection fails
+    fn alert_reporting(&self) -> AlertReporting {
+        match self {
+            // s2n-tls only exposes received alerts
+            Server::S2nTls => AlertReporting::RECEIVED,
+            Server::OpenSSL => AlertReporting::ALL,
+        }
+    }
+}
+
+/// Which of the alerts that a peer sends and receives it reports. TLS
+/// implementations often only expose one of them.
+#[derive(Debug, Copy, Clone)]
+struct AlertReporting {
+    sent: bool,
+    received: bool,
+}
+
+impl AlertReporting {
+    const RECEIVED: Self = Self {
+        sent: false,
+        received: true,
+    };
+    const ALL: Self = Self {
+        sent: true,
+        received: true,
+    };
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -104,6 +194,9 @@ enum TestResult {
     Success,
     Failure,
     Unimplemented,
+    /// The scenario doesn't exist in the TLS version, e.g. KeyUpdate in TLS 1.2,
+    /// or a pinned algorithm doesn't exist in the TLS version
+    NotApplicable,
 }
 
 #[derive(Debug)]
@@ -111,51 +204,288 @@ struct TestScenario {
     client: Client,
     server: Server,
     test_case: InteropTest,
+    parameters: TestParameters,
+}
+
+/// The CPU time and the peak memory of a peer
+#[derive(Debug, Clone, Copy)]
+struct ResourceUsage {
+    cpu_time: Duration,
+    peak_rss_bytes: u64,
+}
+
+impl Display for ResourceUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "cpu={:.2}s rss={:.1}MB",
+            self.cpu_time.as_secs_f64(),
+            self.peak_rss_bytes as f64 / 1_000_000.0
+        )
+    }
+}
+
+/// The resources that both peers used to execute a scenario
+#[derive(Debug, Clone, Copy)]
+struct ScenarioResources {
+    client: ResourceUsage,
+    server: ResourceUsage,
+    /// From the start of the client until both peers exited
+    elapsed: Duration,
+}
+
+impl ScenarioResources {
+    /// The number of cores that the peers kept busy, rounded up
+    fn cores(&self) -> u32 {
+        let cpu_time = self.client.cpu_time + self.server.cpu_time;
+        let cores = cpu_time.as_secs_f64() / self.elapsed.as_secs_f64();
+        (cores.ceil() as u32).max(1)
+    }
+}
+
+impl Display for ScenarioResources {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "client {}, server {}", self.client, self.server)
+    }
+}
+
+/// A peer process. tokio reaps its children without their resource usage, so
+/// on Linux the peer is spawned through std, which doesn't reap children in
+/// the background, and [Peer::wait] is its only reaper. Elsewhere, the peer is
+/// a tokio child and its resource usage isn't measured.
+struct Peer {
+    #[cfg(target_os = "linux")]
+    pid: libc::pid_t,
+    /// Whether the peer was reaped, after which its pid might be reused. The
+    /// lock is held while the peer is reaped or killed.
+    #[cfg(target_os = "linux")]
+    reaped: Arc<Mutex<bool>>,
+    /// The blocking task that reaps the peer, until it finished
+    #[cfg(target_os = "linux")]
+    reaper: Option<tokio::task::JoinHandle<io::Result<(ExitStatus, ResourceUsage)>>>,
+    #[cfg(target_os = "linux")]
+    exit: Option<(ExitStatus, ResourceUsage)>,
+    #[cfg(not(target_os = "linux"))]
+    child: tokio::process::Child,
+}
+
+impl Peer {
+    /// Spawn `command` with its stdout piped
+    fn spawn(command: &mut Command) -> io::Result<(Peer, ChildStdout)> {
+        command.stdout(Stdio::piped());
+        #[cfg(target_os = "linux")]
+        {
+            let mut child = command.as_std_mut().spawn()?;
+            let stdout = ChildStdout::from_std(child.stdout.take().unwrap())?;
+            let pid = child.id() as libc::pid_t;
+            let reaped = Arc::default();
+            let reaper = tokio::task::spawn_blocking({
+                let reaped = Arc::clone(&reaped);
+                move || Peer::reap(pid, &reaped)
+            });
+            let peer = Peer {
+                pid,
+                reaped,
+                reaper: Some(reaper),
+                exit: None,
+            };
+            Ok((peer, stdout))
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            let mut child = command.spawn()?;
+            let stdout = child.stdout.take().unwrap();
+            Ok((Peer { child }, stdout))
+        }
+    }
+
+    /// Wait for `pid` to exit, then reap it and measure the resources that it
+    /// used. The peer is only reaped once it exited, so that it can be killed
+    /// while this is blocked. This is the only waiter of the peer, because a
+    /// second waiter might wait on a reused pid.
+    #[cfg(target_os = "linux")]
+    fn reap(pid: libc::pid_t, reaped: &Mutex<bool>) -> io::Result<(ExitStatus, ResourceUsage)> {
+        // SAFETY: siginfo_t only contains integers, so all zeroes is a valid value
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        // SAFETY: info is valid for writes for the whole call
+        retry_interrupted(|| unsafe {
+            libc::waitid(
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            )
+        })?;
+
+        let mut reaped = reaped.lock().unwrap();
+        let mut status = 0;
+        // SAFETY: rusage only contains integers, so all zeroes is a valid value
+        let mut rusage: libc::rusage = unsafe { std::mem::zeroed() };
+        // SAFETY: status and rusage are valid for writes for the whole call
+        retry_interrupted(|| unsafe { libc::wait4(pid, &mut status, 0, &mut rusage) })?;
+        *reaped = true;
+
+        let duration = |time: libc::timeval| {
+            Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+        };
+        let usage = ResourceUsage {
+            cpu_time: duration(rusage.ru_utime) + duration(rusage.ru_stime),
+            // Linux reports the maximum resident set size in kilobytes
+            peak_rss_bytes: rusage.ru_maxrss as u64 * 1024,
+        };
+        Ok((ExitStatus::from_raw(status), usage))
+    }
+
+    /// Wait for the peer to exit, and return the resources that it used. This
+    /// is cancel safe, and can be called again once the peer exited.
+    #[cfg(target_os = "linux")]
+    async fn wait(&mut self) -> io::Result<(ExitStatus, Option<ResourceUsage>)> {
+        if let Some(reaper) = &mut self.reaper {
+            let exit = reaper.await;
+            self.reaper = None;
+            self.exit = Some(exit??);
+        }
+        let (status, usage) = self
+            .exit
+            .ok_or_else(|| io::Error::other("the peer couldn't be reaped"))?;
+        Ok((status, Some(usage)))
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    async fn wait(&mut self) -> io::Result<(ExitStatus, Option<ResourceUsage>)> {
+        Ok((self.child.wait().await?, None))
+    }
+
+    /// Kill the peer, unless it was already reaped
+    #[cfg(target_os = "linux")]
+    fn kill(&mut self) -> io::Result<()> {
+        let reaped = self.reaped.lock().unwrap();
+        // SAFETY: kill doesn't access memory. The pid still belongs to the
+        // peer, because it isn't reaped while the lock is held.
+        if !*reaped && unsafe { libc::kill(self.pid, libc::SIGKILL) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn kill(&mut self) -> io::Result<()> {
+        self.child.start_kill()
+    }
+}
+
+/// Retry a libc call until it isn't interrupted by a signal
+#[cfg(target_os = "linux")]
+fn retry_interrupted(mut call: impl FnMut() -> libc::c_int) -> io::Result<()> {
+    while call() < 0 {
+        let error = io::Error::last_os_error();
+        if error.kind() != io::ErrorKind::Interrupted {
+            return Err(error);
+        }
+    }
+    Ok(())
 }
 
 impl TestScenario {
-    async fn execute(&mut self, port: u16) -> TestResult {
+    /// The algorithm that is pinned by the scenario, or "default" if the peers
+    /// use their default algorithms
+    fn algorithms(&self) -> String {
+        match (self.parameters.cipher_suite, self.parameters.group) {
+            (None, None) => "default".to_owned(),
+            (Some(cipher_suite), None) => cipher_suite.to_string(),
+            (None, Some(group)) => group.to_string(),
+            (Some(cipher_suite), Some(group)) => format!("{}+{}", cipher_suite, group),
+        }
+    }
+
+    fn log_path(&self, peer: &str) -> String {
+        format!(
+            "interop_logs/{}_{}_{}_{:?}_{:?}_{}.log",
+            self.test_case,
+            self.parameters.tls_version,
+            self.algorithms(),
+            self.server,
+            self.client,
+            peer
+        )
+    }
+
+    /// Execute the scenario. The resources that the peers used are recorded in
+    /// `resources` if both peers exited before the timeout.
+    async fn execute(
+        &mut self,
+        port: u16,
+        resources: &mut Option<ScenarioResources>,
+    ) -> TestResult {
+        let version = self.parameters.tls_version;
+        let tls13_only_algorithm = self.parameters.cipher_suite.is_some()
+            || self.parameters.group.is_some_and(|group| group.is_hybrid());
+        if !self.test_case.supports_tls_version(version)
+            || (tls13_only_algorithm && version != TlsVersion::Tls13)
+        {
+            return TestResult::NotApplicable;
+        }
         let start_time = Instant::now();
         let test_case_name = format!("{}", self.test_case);
 
-        let server_log = format!(
-            "interop_logs/{}_{:?}_{:?}_server.log",
-            self.test_case, self.server, self.client
-        );
-        let client_log = format!(
-            "interop_logs/{}_{:?}_{:?}_client.log",
-            self.test_case, self.server, self.client
-        );
-        let mut server_log = tokio::fs::File::create(server_log).await.unwrap();
-        let mut client_log = tokio::fs::File::create(client_log).await.unwrap();
+        let server_log_path = self.log_path("server");
+        let client_log_path = self.log_path("client");
+        let mut server_log = tokio::fs::File::create(&server_log_path).await.unwrap();
+        let mut client_log = tokio::fs::File::create(&client_log_path).await.unwrap();
 
         // fn executable_path(&self, test_case) -> 
-        let mut server = tokio::process::Command::new(self.server.executable_path())
-            .args([&test_case_name, &port.to_string()])
-            .stdout(Stdio::piped())
-            .spawn()
-            .unwrap();
-        let mut server_stdout = server.stdout.take().unwrap();
+        let (mut server, mut server_stdout) = Peer::spawn(
+            tokio::process::Command::new(self.server.executable_path())
+                .args([&test_case_name, &port.to_string()])
+                .args(self.parameters.to_args()),
+        )
+        .unwrap();
 
         // let the server start up and start listening before starting the client
         sleep(Duration::from_secs(1)).await;
 
+        let mut proxy = None;
+        let mut client_port = port;
+        if self.client.requires_fragmenting_proxy(self.test_case) {
+            let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await.unwrap();
+            client_port = listener.local_addr().unwrap().port();
+            proxy = Some(tokio::spawn(fragmenting_proxy(listener, port)));
+        }
+
+        let client_start_time = Instant::now();
         let mut client_command = tokio::process::Command::new(self.client.executable_path());
-        let mut client = self
-            .client
-            .configure(&mut client_command)
-            .args([&test_case_name, &port.to_string()])
-            .stdout(Stdio::piped())
-            .stderr(Stdio::null())
-            .spawn()
-            .unwrap();
-        let mut client_stdout = client.stdout.take().unwrap();
+        let (mut client, mut client_stdout) = Peer::spawn(
+            self.client
+                .configure(&mut client_command)
+                .args([&test_case_name, &client_port.to_string()])
+                .args(self.parameters.to_args())
+                .stderr(Stdio::null()),
+        )
+        .unwrap();
+
+        let processes = async {
+            let (client_status, client_usage) = client.wait().await?;
+            if client_status.code() != Some(ExitCode::Success.code()) {
+                // a client might fail before it connects, e.g. because it is
+                // unimplemented or can't connect, which would leave the server
+                // waiting for the connection until the timeout
+                server.kill()?;
+            }
+            let (server_status, server_usage) = server.wait().await?;
+            let usage = client_usage
+                .zip(server_usage)
+                .map(|(client, server)| ScenarioResources {
+                    client,
+                    server,
+                    elapsed: client_start_time.elapsed(),
+                });
+            Ok::<_, std::io::Error>((client_status, server_status, usage))
+        };
 
         // wrap everything in a timeout since the "try_join" macro needs everything
         // to have the same error type
         let res = tokio::try_join!(
-            timeout(TEST_TIMEOUT, client.wait()),
-            timeout(TEST_TIMEOUT, server.wait()),
+            timeout(TEST_TIMEOUT, processes),
             // we use tokio::io::copy to copy the println logging of the processes
             // to a log file.
             timeout(
@@ -168,6 +498,13 @@ impl TestScenario {
             ),
         );
 
+        if let Some(proxy) = proxy {
+            proxy.abort();
+        }
+        // make sure that the logs are on disk before they are read back
+        let _ = client_log.flush().await;
+        let _ = server_log.flush().await;
+
         tracing::debug!(
             "{:?} finished in {} seconds",
             self,
@@ -175,28 +512,310 @@ impl TestScenario {
         );
 
         let (c_status, s_status) = match res {
-            Ok((Ok(s), Ok(c), Ok(_), Ok(_))) => (c, s),
-            Err(_) => {
+            Ok((Ok((c_status, s_status, usage)), Ok(_), Ok(_))) => {
+                if let Some(usage) = usage {
+                    tracing::debug!("{:?} used {}", self, usage);
+                }
+                *resources = usage;
+                (c_status, s_status)
+            }
+            res => {
                 // a timeout indicates an "abnormal" exit which must be manually
-                // cleaned up
-                tracing::error!("{:?} timed out", self);
-                server.kill().await.unwrap();
-                client.kill().await.unwrap();
+                // cleaned up. The peers are reaped, so that they don't linger as
+                // zombies, and their resources are still reported.
+                if res.is_err() {
+                    tracing::error!("{:?} timed out", self);
+                }
+                let _ = server.kill();
+                let _ = client.kill();
+                if let (Ok((_, Some(client))), Ok((_, Some(server)))) =
+                    (client.wait().await, server.wait().await)
+                {
+                    *resources = Some(ScenarioResources {
+                        client,
+                        server,
+                        elapsed: client_start_time.elapsed(),
+                    });
+                }
                 return TestResult::Failure;
             }
-            _ => return TestResult::Failure,
         };
-        let c_status = c_status.code().unwrap();
-        let s_status = s_status.code().unwrap();
+        // a server that was killed doesn't have an exit code
+        let c_status = c_status.code();
+        let s_status = s_status.code();
 
-        if c_status == UNIMPLEMENTED_RETURN_VAL || s_status == UNIMPLEMENTED_RETURN_VAL {
+        let unimplemented = Some(ExitCode::Unimplemented.code());
+        let success = Some(ExitCode::Success.code());
+        if c_status == unimplemented || s_status == unimplemented {
             TestResult::Unimplemented
-        } else if c_status == 0 && s_status == 0 {
+        } else if c_status == success && s_status == success {
+            if self.test_case.expects_handshake_failure() {
+                self.check_expected_failure(&client_log_path, &server_log_path)
+                    .await
+            } else {
+                self.check_negotiated_params(&client_log_path, &server_log_path)
+                    .await
+            }
+        } else {
+            tracing::error!(
+                "{:?} failed, client: {}, server: {}",
+                self,
+                describe_exit(c_status),
+                describe_exit(s_status)
+            );
+            TestResult::Failure
+        }
+    }
+
+    /// Collect the metrics that the peers reported for the connections that ran
+    /// the scenario to completion, in order. Invalid metrics don't fail the
+    /// test, because they don't affect the interoperability of the peers.
+    async fn collect_metrics(&self) -> Vec<(&'static str, ScenarioMetrics)> {
+        let mut collected = Vec::new();
+        for peer in ["client", "server"] {
+            let log = tokio::fs::read(self.log_path(peer))
+                .await
+                .unwrap_or_default();
+            for metrics in ScenarioMetrics::from_log(&String::from_utf8_lossy(&log)) {
+                match metrics {
+                    Ok(metrics) => collected.push((peer, metrics)),
+                    Err(e) => tracing::warn!("{:?} {} reported invalid metrics: {}", self, peer, e),
+                }
+            }
+        }
+        collected
+    }
+
+    /// The rows of the metrics file for this scenario
+    fn metrics_rows(&self, metrics: &[(&'static str, ScenarioMetrics)]) -> Vec<String> {
+        let millis = |d: Option<Duration>| {
+            d.map(|d| format!("{:.3}", d.as_secs_f64() * 1_000.0))
+                .unwrap_or_default()
+        };
+        let count = |c: Option<u64>| c.map(|c| c.to_string()).unwrap_or_default();
+        let mut connections = [0, 0];
+        metrics
+            .iter()
+            .map(|(peer, m)| {
+                let connection = &mut connections[(*peer == "server") as usize];
+                *connection += 1;
+                [
+                    self.test_case.to_string(),
+                    self.parameters.tls_version.to_string(),
+                    self.algorithms(),
+                    format!("{:?}", self.server),
+                    format!("{:?}", self.client),
+                    peer.to_string(),
+                    connection.to_string(),
+                    millis(m.handshake),
+                    millis(m.time_to_first_byte),
+                    millis(m.transfer),
+                    count(m.data_bytes),
+                    m.throughput_bytes_per_s()
+                        .map(|t| format!("{:.0}", t))
+                        .unwrap_or_default(),
+                    count(m.key_updates_sent),
+                    count(m.key_updates_received),
+                ]
+                .join(",")
+            })
+            .collect()
+    }
+
+    /// Compare the parameters that each peer reported that it negotiated. A
+    /// disagreement between the peers is an interop failure.
+    async fn check_negotiated_params(&self, client_log: &str, server_log: &str) -> TestResult {
+        let read_params = |log: Vec<u8>| NegotiatedParams::from_log(&String::from_utf8_lossy(&log));
+        let client = read_params(tokio::fs::read(client_log).await.unwrap_or_default());
+        let server = read_params(tokio::fs::read(server_log).await.unwrap_or_default());
+
+        let (client, server) = match (client, server) {
+            (Some(Ok(client)), Some(Ok(server))) => (client, server),
+            (Some(Err(e)), _) | (_, Some(Err(e))) => {
+                tracing::error!("{:?} reported invalid negotiated parameters: {}", self, e);
+                return TestResult::Failure;
+            }
+            // not every implementation reports the negotiated parameters
+            _ => return TestResult::Success,
+        };
+
+        let requested = [
+            ("version", Some(self.parameters.tls_version.negotiated_name())),
+            ("cipher_suite", self.parameters.cipher_suite.map(|c| c.iana_name())),
+            (
+                "group",
+                self.parameters.group_for(self.test_case).map(|g| g.iana_name()),
+            ),
+        ];
+        for (peer, params) in [("client", &client), ("server", &server)] {
+            let negotiated = [
+                &params.protocol_version,
+                &params.cipher_suite,
+                &params.key_exchange_group,
+            ];
+            for ((name, requested), negotiated) in requested.iter().zip(negotiated) {
+                if let (Some(requested), Some(negotiated)) = (requested, negotiated) {
+                    if !negotiated.eq_ignore_ascii_case(requested) {
+                        tracing::error!(
+                            "{:?} {} negotiated {} {}, but only {} was enabled",
+                            self,
+                            peer,
+                            name,
+                            negotiated,
+                            requested
+                        );
+                        return TestResult::Failure;
+                    }
+                }
+            }
+        }
+
+        let disagreements = client.disagreements(&server);
+        if disagreements.is_empty() {
+            TestResult::Success
+        } else {
+            tracing::error!(
+                "{:?} peers disagree on the negotiated parameters: {:?}",
+                self,
+                disagreements
+            );
+            TestResult::Failure
+        }
+    }
+
+    /// In scenarios that are expected to fail, both peers must report that
+    /// they observed the failure. A clean exit without that report might hide
+    /// an unrelated error.
+    async fn check_expected_failure(&self, client_log: &str, server_log: &str) -> TestResult {
+        let client = tokio::fs::read_to_string(client_log)
+            .await
+            .unwrap_or_default();
+        let server = tokio::fs::read_to_string(server_log)
+            .await
+            .unwrap_or_default();
+        let observed = |log: &str| log.contains(EXPECTED_FAILURE_LOG);
+        if !(observed(&client) && observed(&server)) {
+            let peers: Vec<&str> = [(&client, "client"), (&server, "server")]
+                .into_iter()
+                .filter_map(|(log, peer)| (!observed(log)).then_some(peer))
+                .collect();
+            tracing::error!(
+                "{:?} exited cleanly, but the expected failure wasn't observed by the {}",
+                self,
+                peers.join(" or ")
+            );
+            return TestResult::Failure;
+        }
+
+        let client_alerts = self.check_alerts("client", &client);
+        let server_alerts = self.check_alerts("server", &server);
+        if client_alerts && server_alerts && self.check_alert_reported(&client, &server) {
             TestResult::Success
         } else {
             TestResult::Failure
         }
     }
+
+    /// If the peer that sends the alert reports sent alerts, or the peer that
+    /// receives it reports received alerts, then the alert must be reported.
+    /// A connection that failed without an alert hides which check failed.
+    fn check_alert_reported(&self, client_log: &str, server_log: &str) -> bool {
+        let client = self.client.alert_reporting();
+        let server = self.server.alert_reporting();
+        let (sender, receiver) = if self.test_case.client_sends_alert() {
+            (client, server)
+        } else {
+            (server, client)
+        };
+        if !(sender.sent || receiver.received) {
+            return true;
+        }
+        let reported = [client_log, server_log]
+            .iter()
+            .any(|log| !TlsAlert::from_log(log).is_empty());
+        if !reported {
+            tracing::error!("{:?} failed without an alert from either peer", self);
+        }
+        reported
+    }
+
+    /// Every alert that a peer reports must be one of the alerts that the
+    /// scenario expects. Mismatched alerts are interop bugs, even though the
+    /// connection fails either way.
+    fn check_alerts(&self, peer: &str, log: &str) -> bool {
+        let expected = self.test_case.expected_alerts();
+        let mut valid = true;
+        for alert in TlsAlert::from_log(log) {
+            match alert {
+                Ok(alert) if expected.contains(&alert.description()) => {
+                    tracing::info!("{:?} {} reported {}", self, peer, alert);
+                }
+                Ok(alert) => {
+                    tracing::error!(
+                        "{:?} {} reported {}, but expected one of {:?}",
+                        self,
+                        peer,
+                        alert,
+                        expected.iter().map(ToString::to_string).collect::<Vec<_>>()
+                    );
+                    valid = false;
+                }
+                Err(e) => {
+                    tracing::error!("{:?} {} reported an invalid alert: {}", self, peer, e);
+                    valid = false;
+                }
+            }
+        }
+        valid
+    }
+}
+
+/// Describe how a peer exited, according to the [ExitCode] contract.
+fn describe_exit(code: Option<i32>) -> String {
+    match code {
+        Some(code) => match ExitCode::from_code(code) {
+            Some(exit_code) => exit_code.to_string(),
+            None => format!("crashed with exit code {}", code),
+        },
+        None => "killed".to_owned(),
+    }
+}
+
+/// Accept a single client connection on `listener` and relay it to the server
+/// listening on `server_port`. Each write made by the proxy carries at most
+/// SMALL_TCP_PACKET_BYTES.
+async fn fragmenting_proxy(listener: TcpListener, server_port: u16) {
+    let relay = async {
+        let (client, _) = listener.accept().await?;
+        let server = TcpStream::connect((Ipv4Addr::LOCALHOST, server_port)).await?;
+        client.set_nodelay(true)?;
+        server.set_nodelay(true)?;
+
+        let (mut client_read, mut client_write) = client.into_split();
+        let (mut server_read, mut server_write) = server.into_split();
+        tokio::try_join!(
+            relay_fragments(&mut client_read, &mut server_write),
+            relay_fragments(&mut server_read, &mut client_write),
+        )
+    };
+    if let Err(e) = relay.await {
+        tracing::warn!("fragmenting proxy for port {server_port} failed: {e}");
+    }
+}
+
+async fn relay_fragments(
+    reader: &mut (impl AsyncRead + Unpin),
+    writer: &mut (impl AsyncWrite + Unpin),
+) -> std::io::Result<()> {
+    let mut fragment = [0; SMALL_TCP_PACKET_BYTES];
+    loop {
+        let read = reader.read(&mut fragment).await?;
+        if read == 0 {
+            // forward the half close to the peer
+            return writer.shutdown().await;
+        }
+        writer.write_all(&fragment[..read]).await?;
+    }
 }
 
 #[tokio::main]
@@ -206,64 +825,186 @@ async fn main() {
         .with_ansi(false)
         .init();
 
+    // PR CI uses the quick data volume by default, nightly runs should pass
+    // `--data-volume full`
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let parameters = TestParameters {
+        data_volume: DataVolume::QUICK,
+        ..Default::default()
+    }
+    .with_flags(&args)
+    .unwrap();
+    // every scenario runs with each TLS version, unless a single version is
+    // requested with `--tls-version`
+    let tls_versions = if args.iter().any(|arg| arg == "--tls-version") {
+        vec![parameters.tls_version]
+    } else {
+        TlsVersion::ALL.to_vec()
+    };
+    tracing::info!("running with {:?} for {:?}", parameters, tls_versions);
+    // the algorithm matrix is skipped if an algorithm is pinned with
+    // `--cipher-suite` or `--group`
+    let pinned_algorithms: Vec<(Option<CipherSuite>, Option<KeyExchangeGroup>)> =
+        if parameters.cipher_suite.is_some() || parameters.group.is_some() {
+            Vec::new()
+        } else {
+            CipherSuite::ALL
+                .into_iter()
+                .map(|cipher_suite| (Some(cipher_suite), None))
+                // hybrid groups are covered by the pq_handshake scenario
+                .chain(
+                    KeyExchangeGroup::ALL
+                        .into_iter()
+                        .filter(|group| !group.is_hybrid())
+                        .map(|group| (None, Some(group))),
+                )
+                .collect()
+        };
+
     tokio::fs::create_dir_all("interop_logs").await.unwrap();
 
-    let clients = vec![Client::S2nTls, Client::Rustls, Client::Java, Client::Go];
-    //let clients = vec![Client::Java];
-    let servers = vec![Server::S2nTls, Server::OpenSSL];
+    let clients = [Client::S2nTls, Client::Rustls, Client::Java, Client::Go];
+    //let clients = [Client::Java];
+    let servers = [Server::S2nTls, Server::OpenSSL];
 
     let mut scenarios = Vec::new();
 
     for t in ENABLED_TESTS {
-        for s in servers.iter() {
-            for c in clients.iter() {
-                scenarios.push(TestScenario {
-                    client: *c,
-                    server: *s,
-                    test_case: t,
-                })
+        for v in tls_versions.iter() {
+            for s in servers.iter() {
+                for c in clients.iter() {
+                    scenarios.push(TestScenario {
+                        client: *c,
+                        server: *s,
+                        test_case: t,
+                        parameters: TestParameters {
+                            tls_version: *v,
+                            ..parameters
+                        },
+                    })
+                }
+            }
+        }
+    }
+    // the cipher suites are TLS 1.3 cipher suites, so the matrix is only
+    // executed with TLS 1.3
+    if tls_versions.contains(&TlsVersion::Tls13) {
+        for t in ALGORITHM_MATRIX_TESTS {
+            for (cipher_suite, group) in pinned_algorithms.iter() {
+                for s in servers.iter() {
+                    for c in clients.iter() {
+                        scenarios.push(TestScenario {
+                            client: *c,
+                            server: *s,
+                            test_case: t,
+                            parameters: TestParameters {
+                                tls_version: TlsVersion::Tls13,
+                                cipher_suite: *cipher_suite,
+                                group: *group,
+                                ..parameters
+                            },
+                        })
+                    }
+                }
             }
         }
     }
 
     let (results_tx, mut results_rx) = unbounded_channel();
     let mut results = Vec::new();
-    // The large tests are capable of saturating 2 cores (1 for the client and 1
-    // for the server) so we limit the number of concurrent tests to NUM_CORES / 2
-    let concurrent_tests = thread::available_parallelism().unwrap().get() / 2;
-    tracing::debug!("Setting concurrency to {concurrent_tests}");
-    let concurrent_tests = Arc::new(Semaphore::new(concurrent_tests));
-    for (i, mut scenario) in scenarios.into_iter().enumerate() {
-        let results_tx_handle = results_tx.clone();
-        let test_limiter_handle = Arc::clone(&concurrent_tests);
-        tokio::spawn(async move {
-            let ticket = test_limiter_handle.acquire().await.unwrap();
-            let result = scenario.execute(PORT_RANGE_START + (i as u16)).await;
-            drop(ticket);
-            // something has gone drastically wrong if this panics, so use unwrap
-            results_tx_handle.send((scenario, result)).unwrap();
-        });
-    }
-    // we manually drop results_tx, because the channel will never return "None"
-    // on a read if there is a sender still open
-    drop(results_tx);
-
-    while let Some((scenario, result)) = results_rx.recv().await {
+    let mut metrics_rows = Vec::new();
+    // Each scenario holds a permit for each core that it keeps busy. The cost of
+    // a test case is the most that any of its scenarios used so far, which is
+    // measured when the scenario finishes.
+    let cores = thread::available_parallelism().map_or(1, |n| n.get()) as u32;
+    tracing::debug!("scheduling scenarios on {cores} cores");
+    let core_limiter = Arc::new(Semaphore::new(cores as usize));
+    let costs: Arc<Mutex<HashMap<InteropTest, u32>>> = Arc::default();
+    // the dispatcher owns results_tx, so the channel returns "None" once the
+    // dispatcher and every scenario are done
+    tokio::spawn(async move {
+        for (i, mut scenario) in scenarios.into_iter().enumerate() {
+            // the cost is read when the scenario is next in line, so that it
+            // includes the measurements of the scenarios that finished meanwhile
+            let cost = costs
+                .lock()
+                .unwrap()
+                .get(&scenario.test_case)
+                .copied()
+                .unwrap_or(DEFAULT_SCENARIO_CORES)
+                .min(cores);
+            let ticket = Arc::clone(&core_limiter)
+                .acquire_many_owned(cost)
+                .await
+                .unwrap();
+            let results_tx_handle = results_tx.clone();
+            let costs_handle = Arc::clone(&costs);
+            tokio::spawn(async move {
+                let mut resources = None;
+                let result = scenario
+                    .execute(PORT_RANGE_START + (i as u16), &mut resources)
+                    .await;
+                drop(ticket);
+                if let Some(resources) = resources {
+                    let mut costs = costs_handle.lock().unwrap();
+                    let cost = costs.entry(scenario.test_case).or_default();
+                    *cost = (*cost).max(resources.cores());
+                }
+                let metrics = scenario.collect_metrics().await;
+                // something has gone drastically wrong if this panics, so use unwrap
+                results_tx_handle
+                    .send((scenario, result, metrics, resources))
+                    .unwrap();
+            });
+        }
+    });
+
+    while let Some((scenario, result, metrics, resources)) = results_rx.recv().await {
         tracing::info!("{:?} finished with {:?}", scenario, result);
+        metrics_rows.extend(scenario.metrics_rows(&metrics));
         let result = match result {
             TestResult::Success => "🥳",
             TestResult::Failure => "💔",
             TestResult::Unimplemented => "🚧",
+            TestResult::NotApplicable => "➖",
         }.to_owned();
 
-        results.push((scenario.test_case, scenario.server, scenario.client, result));
+        results.push((
+            scenario.test_case,
+            scenario.parameters.tls_version,
+            scenario.algorithms(),
+            scenario.server,
+            scenario.client,
+            result,
+            resources.map(|r| r.to_string()).unwrap_or_default(),
+        ));
         results.sort();
         print_results_table(&results);
     }
+
+    metrics_rows.sort();
+    let header = "test,version,algorithms,server,client,peer,connection,handshake_ms,ttfb_ms,\
+                  transfer_ms,data_bytes,throughput_bytes_per_s,key_updates_sent,key_updates_received";
+    let metrics = std::iter::once(header.to_owned())
+        .chain(metrics_rows)
+        .map(|row| row + "\n")
+        .collect::<String>();
+    tokio::fs::write(METRICS_PATH, metrics).await.unwrap();
+    tracing::info!("metrics were written to {}", METRICS_PATH);
 }
 
-fn print_results_table(results: &Vec<(InteropTest, Server, Client, String)>) {
-    for (test, server, client, result) in results {
-        println!("{:23}, {:10}, {:10}, {}", test.to_string(), format!("{:?}",server), format!("{:?}",client), result);
+type Results = Vec<(
+    InteropTest,
+    TlsVersion,
+    String,
+    Server,
+    Client,
+    String,
+    String,
+)>;
+
+fn print_results_table(results: &Results) {
+    for (test, version, algorithms, server, client, result, resources) in results {
+        println!("{:23}, {:6}, {:28}, {:10}, {:10}, {}, {}", test.to_string(), version.to_string(), algorithms, format!("{:?}",server), format!("{:?}",client), result, resources);
     }
 }
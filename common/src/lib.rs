@@ -1,25 +1,109 @@
-use std::{env, fmt::Display, str::FromStr};
+//! `Common` provides a crate with functionality that other TLS implementors
+//! might find useful if they are implementing a rust shim.
 
-/// `Common` provides a crate with functionality that other TLS implementors
-/// might find useful if they are implementing a rust shim.
+use std::{fmt::Display, str::FromStr, time::Duration};
 
 /// This message is send to the server at the start of several test cases
 pub const CLIENT_GREETING: &str = "i am the client. nice to meet you server.";
 /// This short message is send after the client greeting in the "GREETING" scenario
 pub const SERVER_GREETING: &str = "i am the server. a pleasure to make your acquaintance.";
-/// The amount of data that will be downloaded by the large download test. Note 
-/// that the interop tests use a GB as 1_000^3 bytes, not 1_024^3 bytes
-pub const LARGE_DATA_DOWNLOAD_GB: u64 = 256;
-/// If a server or client doesn't support a test case, then the process should
-/// exit with this value.
-pub const UNIMPLEMENTED_RETURN_VAL: i32 = 127;
+/// In the small TCP packet scenario, the transport delivers at most this many
+/// bytes with each read or write
+pub const SMALL_TCP_PACKET_BYTES: usize = 1;
+/// The name that the server certificate is issued for. Clients which don't send
+/// SNI should authenticate the server with this name.
+pub const SERVER_NAME: &str = "localhost";
+/// The name that the alternate server certificate is issued for. Servers should
+/// only select the alternate certificate if the client requests this name
+/// through SNI.
+pub const ALTERNATE_SERVER_NAME: &str = "alternate.localhost";
+/// Peers log a line containing this marker when the connection fails in a
+/// scenario that is expected to fail. This allows the runner to distinguish the
+/// expected failure from an unrelated error that still exits cleanly.
+pub const EXPECTED_FAILURE_LOG: &str = "failed as expected";
 
+/// The exit codes that every shim uses to report the outcome of a scenario, so
+/// that the runner can tell why a scenario failed.
+///
+/// Invalid arguments and configuration errors are reported as an
+/// [ExitCode::AssertionFailure], because the shim can't run the scenario that
+/// it was asked to run. A shim that crashes, e.g. with a Rust panic or an
+/// uncaught exception, might exit with any other code.
+///
+/// The Go and Java clients mirror these definitions, so they must be kept in sync.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExitCode {
+    /// The scenario completed, and all of its assertions held
+    Success = 0,
+    /// The peer misbehaved, e.g. it sent the wrong data, or the connection
+    /// didn't have the expected properties, e.g. the session wasn't resumed
+    AssertionFailure = 1,
+    /// The handshake failed, or the connection was closed with a TLS alert
+    HandshakeFailure = 2,
+    /// The transport failed, e.g. the connection was refused, or closed before
+    /// the scenario completed
+    IoError = 3,
+    /// The shim gave up waiting for its peer
+    Timeout = 4,
+    /// The implementation doesn't support the scenario
+    Unimplemented = 127,
+}
+
+impl ExitCode {
+    pub const ALL: [ExitCode; 6] = [
+        ExitCode::Success,
+        ExitCode::AssertionFailure,
+        ExitCode::HandshakeFailure,
+        ExitCode::IoError,
+        ExitCode::Timeout,
+        ExitCode::Unimplemented,
+    ];
+
+    pub fn code(&self) -> i32 {
+        *self as i32
+    }
+
+    /// The exit code with the value `code`, if it is part of the contract.
+    pub fn from_code(code: i32) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|exit_code| exit_code.code() == code)
+    }
+
+    /// Exit the process with this code.
+    pub fn exit(&self) -> ! {
+        std::process::exit(self.code())
+    }
+}
+
+impl Display for ExitCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExitCode::Success => write!(f, "success"),
+            ExitCode::AssertionFailure => write!(f, "assertion failure"),
+            ExitCode::HandshakeFailure => write!(f, "handshake failure"),
+            ExitCode::IoError => write!(f, "i/o error"),
+            ExitCode::Timeout => write!(f, "timeout"),
+            ExitCode::Unimplemented => write!(f, "unimplemented"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PemType {
     CaCert,
     ServerChain,
     ServerKey,
     ClientChain,
     ClientKey,
+    AlternateServerChain,
+    AlternateServerKey,
+    ExpiredServerChain,
+    ExpiredServerKey,
+    UntrustedServerChain,
+    UntrustedServerKey,
+    UntrustedClientChain,
+    UntrustedClientKey,
 }
 
 pub fn pem_directory() -> &'static str {
@@ -57,22 +141,729 @@ pub fn pem_file_path(file: PemType) -> &'static str {
             "/..",
             "/certificates/client-key.pem"
         ),
+        PemType::AlternateServerChain => concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/..",
+            "/certificates/alternate-server-chain.pem"
+        ),
+        PemType::AlternateServerKey => concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/..",
+            "/certificates/alternate-server-key.pem"
+        ),
+        PemType::ExpiredServerChain => concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/..",
+            "/certificates/expired-server-chain.pem"
+        ),
+        PemType::ExpiredServerKey => concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/..",
+            "/certificates/expired-server-key.pem"
+        ),
+        PemType::UntrustedServerChain => concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/..",
+            "/certificates/untrusted-server-chain.pem"
+        ),
+        PemType::UntrustedServerKey => concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/..",
+            "/certificates/untrusted-server-key.pem"
+        ),
+        PemType::UntrustedClientChain => concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/..",
+            "/certificates/untrusted-client-cert.pem"
+        ),
+        PemType::UntrustedClientKey => concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/..",
+            "/certificates/untrusted-client-key.pem"
+        ),
+    }
+}
+
+/// Scenario parameters which are supplied by the runner in addition to the test
+/// case. A shim that isn't given a parameter uses the default value.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TestParameters {
+    /// The amount of data transferred by the large data scenarios
+    pub data_volume: DataVolume,
+    /// The only protocol version that the peers enable
+    pub tls_version: TlsVersion,
+    /// The only TLS 1.3 cipher suite that the peers enable. If this is `None`
+    /// the peers use their default cipher suites.
+    pub cipher_suite: Option<CipherSuite>,
+    /// The only key exchange group that the peers enable. If this is `None`
+    /// the peers use their default groups.
+    pub group: Option<KeyExchangeGroup>,
+}
+
+impl TestParameters {
+    /// Override the parameters with the `--flag value` pairs in `flags`.
+    pub fn with_flags(mut self, flags: &[String]) -> Result<Self, String> {
+        let mut flags = flags.iter();
+        while let Some(flag) = flags.next() {
+            let value = flags
+                .next()
+                .ok_or_else(|| format!("missing value for {}", flag))?;
+            match flag.as_str() {
+                "--data-volume" => self.data_volume = value.parse()?,
+                "--tls-version" => self.tls_version = value.parse()?,
+                "--cipher-suite" => self.cipher_suite = Some(value.parse()?),
+                "--group" => self.group = Some(value.parse()?),
+                _ => return Err(format!("unrecognized argument: {}", flag)),
+            }
+        }
+        Ok(self)
+    }
+
+    /// The only key exchange group that the peers enable in `test`. A group
+    /// that is required by the scenario takes precedence over the pinned group.
+    pub fn group_for(&self, test: InteropTest) -> Option<KeyExchangeGroup> {
+        test.required_group().or(self.group)
+    }
+
+    /// Format the parameters as the command line flags that the shims accept
+    /// after the test case and the port.
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = vec![
+            "--data-volume".to_owned(),
+            self.data_volume.to_string(),
+            "--tls-version".to_owned(),
+            self.tls_version.to_string(),
+        ];
+        if let Some(cipher_suite) = self.cipher_suite {
+            args.extend(["--cipher-suite".to_owned(), cipher_suite.to_string()]);
+        }
+        if let Some(group) = self.group {
+            args.extend(["--group".to_owned(), group.to_string()]);
+        }
+        args
+    }
+}
+
+/// A TLS 1.3 cipher suite. Cipher suites are displayed with their IANA names,
+/// which are also the names that peers report in the [NegotiatedParams].
+///
+/// The Go and Java clients mirror these definitions, so they must be kept in sync.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum CipherSuite {
+    Aes128GcmSha256,
+    Aes256GcmSha384,
+    Chacha20Poly1305Sha256,
+}
+
+impl CipherSuite {
+    pub const ALL: [CipherSuite; 3] = [
+        CipherSuite::Aes128GcmSha256,
+        CipherSuite::Aes256GcmSha384,
+        CipherSuite::Chacha20Poly1305Sha256,
+    ];
+
+    pub fn iana_name(&self) -> &'static str {
+        match self {
+            CipherSuite::Aes128GcmSha256 => "TLS_AES_128_GCM_SHA256",
+            CipherSuite::Aes256GcmSha384 => "TLS_AES_256_GCM_SHA384",
+            CipherSuite::Chacha20Poly1305Sha256 => "TLS_CHACHA20_POLY1305_SHA256",
+        }
+    }
+}
+
+impl FromStr for CipherSuite {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|suite| suite.iana_name() == s)
+            .ok_or_else(|| format!("unrecognized cipher suite: {}", s))
+    }
+}
+
+impl Display for CipherSuite {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.iana_name())
+    }
+}
+
+/// A key exchange group. Groups are displayed with their IANA names, which
+/// peers also report in the [NegotiatedParams], although some implementations
+/// capitalize them differently.
+///
+/// The Go and Java clients mirror these definitions, so they must be kept in sync.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum KeyExchangeGroup {
+    X25519,
+    Secp256r1,
+    Secp384r1,
+    /// The hybrid of X25519 and ML-KEM-768 from draft-ietf-tls-ecdhe-mlkem
+    X25519Mlkem768,
+}
+
+impl KeyExchangeGroup {
+    pub const ALL: [KeyExchangeGroup; 4] = [
+        KeyExchangeGroup::X25519,
+        KeyExchangeGroup::Secp256r1,
+        KeyExchangeGroup::Secp384r1,
+        KeyExchangeGroup::X25519Mlkem768,
+    ];
+
+    pub fn iana_name(&self) -> &'static str {
+        match self {
+            KeyExchangeGroup::X25519 => "x25519",
+            KeyExchangeGroup::Secp256r1 => "secp256r1",
+            KeyExchangeGroup::Secp384r1 => "secp384r1",
+            KeyExchangeGroup::X25519Mlkem768 => "X25519MLKEM768",
+        }
+    }
+
+    /// Returns true if the group combines a classical key exchange with a
+    /// post-quantum KEM. Hybrid groups only exist in TLS 1.3.
+    pub fn is_hybrid(&self) -> bool {
+        matches!(self, KeyExchangeGroup::X25519Mlkem768)
+    }
+}
+
+impl FromStr for KeyExchangeGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|group| group.iana_name() == s)
+            .ok_or_else(|| format!("unrecognized group: {}", s))
+    }
+}
+
+impl Display for KeyExchangeGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.iana_name())
+    }
+}
+
+/// The protocol version of a scenario. Peers only enable this version, so a
+/// peer that doesn't support it fails the handshake rather than falling back to
+/// a different version.
+///
+/// The Go and Java clients mirror these definitions, so they must be kept in sync.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum TlsVersion {
+    Tls12,
+    #[default]
+    Tls13,
+}
+
+impl TlsVersion {
+    pub const ALL: [TlsVersion; 2] = [TlsVersion::Tls13, TlsVersion::Tls12];
+
+    /// The name that peers report in the [NegotiatedParams] for this version
+    pub fn negotiated_name(&self) -> &'static str {
+        match self {
+            TlsVersion::Tls12 => "TLSv1.2",
+            TlsVersion::Tls13 => "TLSv1.3",
+        }
+    }
+}
+
+impl FromStr for TlsVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tls1.2" => Ok(TlsVersion::Tls12),
+            "tls1.3" => Ok(TlsVersion::Tls13),
+            _ => Err(format!("unrecognized tls version: {}", s)),
+        }
+    }
+}
+
+impl Display for TlsVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsVersion::Tls12 => write!(f, "tls1.2"),
+            TlsVersion::Tls13 => write!(f, "tls1.3"),
+        }
     }
 }
 
-/// This method is used to parse the server arguments from the command line argv.
+/// The amount of data transferred by the large data scenarios.
 ///
-/// It will the return the [InteropTest] that is being run, as well as the expected
-/// port for the server to run on.
-pub fn parse_server_arguments() -> (InteropTest, u16) {
-    let args: Vec<String> = env::args().skip(1).collect();
-    let test: InteropTest = args
-        .get(0)
-        .expect("you must supply command line arguments")
-        .parse()
-        .unwrap();
-    let port = args[1].parse().unwrap();
-    (test, port)
+/// Data is sent in `blocks`, and each block is made up of `mb_per_block` 1 MB
+/// writes. The first byte of each MB is equal to the index of the block that it
+/// belongs to (mod 255). Scenarios with frequent key updates update the sending
+/// key once per block. Note that the interop tests use a MB as 1_000^2 bytes,
+/// not 1_024^2 bytes.
+///
+/// The Go and Java clients mirror these definitions, so they must be kept in sync.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct DataVolume {
+    pub blocks: u64,
+    pub mb_per_block: u64,
+}
+
+impl DataVolume {
+    /// A few MB of data, which is appropriate for PR CI and simulation
+    pub const QUICK: DataVolume = DataVolume {
+        blocks: 8,
+        mb_per_block: 1,
+    };
+    /// 256 GB of data. This number is chosen to be higher than the default key
+    /// update limits that most implementations have set
+    pub const FULL: DataVolume = DataVolume {
+        blocks: 256,
+        mb_per_block: 1_000,
+    };
+
+    /// The number of bytes in a MB
+    pub const BYTES_PER_MB: u64 = 1_000_000;
+
+    pub fn total_mb(&self) -> u64 {
+        self.blocks * self.mb_per_block
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.total_mb() * Self::BYTES_PER_MB
+    }
+
+    /// The tag that is expected as the first byte of each MB in `block`
+    pub fn tag(block: u64) -> u8 {
+        (block % u8::MAX as u64) as u8
+    }
+}
+
+impl Default for DataVolume {
+    fn default() -> Self {
+        DataVolume::FULL
+    }
+}
+
+/// Data volumes are either one of the named profiles (`quick`, `full`) or an
+/// explicit `{blocks}x{mb_per_block}` size, e.g. `4x250`.
+impl FromStr for DataVolume {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "quick" => return Ok(DataVolume::QUICK),
+            "full" => return Ok(DataVolume::FULL),
+            _ => {}
+        }
+        let invalid = || format!("unrecognized data volume: {}", s);
+        let (blocks, mb_per_block) = s.split_once('x').ok_or_else(invalid)?;
+        Ok(DataVolume {
+            blocks: blocks.parse().map_err(|_| invalid())?,
+            mb_per_block: mb_per_block.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+impl Display for DataVolume {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            DataVolume::QUICK => write!(f, "quick"),
+            DataVolume::FULL => write!(f, "full"),
+            DataVolume {
+                blocks,
+                mb_per_block,
+            } => write!(f, "{}x{}", blocks, mb_per_block),
+        }
+    }
+}
+
+/// The parameters that a peer negotiated on a connection. Each field is `None`
+/// if the TLS implementation doesn't expose that parameter.
+///
+/// Shims report their view of the connection by logging the [Display] form,
+/// which the runner parses out of the logs to compare the views of both peers.
+/// Values use the IANA names, e.g. `TLS_AES_128_GCM_SHA256` or `x25519`, and
+/// are compared case insensitively.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NegotiatedParams {
+    pub protocol_version: Option<String>,
+    pub cipher_suite: Option<String>,
+    pub key_exchange_group: Option<String>,
+    pub signature_scheme: Option<String>,
+    /// `Some(None)` indicates that no protocol was negotiated
+    pub alpn: Option<Option<String>>,
+    /// `Some(None)` indicates that no server name was sent
+    pub sni: Option<Option<String>>,
+    pub resumed: Option<bool>,
+    pub hello_retry: Option<bool>,
+}
+
+impl NegotiatedParams {
+    /// Marks the log line that contains the negotiated parameters
+    pub const LOG_PREFIX: &'static str = "negotiated_params";
+    /// Logged in place of an `alpn` or `sni` value that wasn't negotiated
+    const NONE: &'static str = "-";
+
+    /// Find the negotiated parameters in the output of a shim. If the shim
+    /// reported more than one connection, the last one is returned.
+    pub fn from_log(log: &str) -> Option<Result<Self, String>> {
+        log.lines()
+            .rev()
+            .find_map(|line| line.find(Self::LOG_PREFIX).map(|i| &line[i..]))
+            .map(str::parse)
+    }
+
+    /// Return a description of each parameter that both peers reported, but
+    /// which doesn't match.
+    pub fn disagreements(&self, peer: &Self) -> Vec<String> {
+        fn differs<T: Display>(name: &str, a: &Option<T>, b: &Option<T>) -> Option<String> {
+            let (a, b) = (a.as_ref()?.to_string(), b.as_ref()?.to_string());
+            (!a.eq_ignore_ascii_case(&b)).then(|| format!("{}: {} != {}", name, a, b))
+        }
+        let alpn = |p: &Self| p.alpn.as_ref().map(|v| Self::display_optional(v).to_owned());
+        let sni = |p: &Self| p.sni.as_ref().map(|v| Self::display_optional(v).to_owned());
+        [
+            differs("version", &self.protocol_version, &peer.protocol_version),
+            differs("cipher_suite", &self.cipher_suite, &peer.cipher_suite),
+            differs("group", &self.key_exchange_group, &peer.key_exchange_group),
+            differs("signature_scheme", &self.signature_scheme, &peer.signature_scheme),
+            differs("alpn", &alpn(self), &alpn(peer)),
+            differs("sni", &sni(self), &sni(peer)),
+            differs("resumed", &self.resumed, &peer.resumed),
+            differs("hello_retry", &self.hello_retry, &peer.hello_retry),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    fn display_optional(value: &Option<String>) -> &str {
+        value.as_deref().unwrap_or(Self::NONE)
+    }
+}
+
+/// Parameters are formatted as `negotiated_params key=value key=value`, where
+/// parameters that weren't reported are omitted.
+impl Display for NegotiatedParams {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::LOG_PREFIX)?;
+        let fields = [
+            ("version", self.protocol_version.clone()),
+            ("cipher_suite", self.cipher_suite.clone()),
+            ("group", self.key_exchange_group.clone()),
+            ("signature_scheme", self.signature_scheme.clone()),
+            ("alpn", self.alpn.as_ref().map(|v| Self::display_optional(v).to_owned())),
+            ("sni", self.sni.as_ref().map(|v| Self::display_optional(v).to_owned())),
+            ("resumed", self.resumed.map(|v| v.to_string())),
+            ("hello_retry", self.hello_retry.map(|v| v.to_string())),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                write!(f, " {}={}", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for NegotiatedParams {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split_whitespace();
+        if fields.next() != Some(Self::LOG_PREFIX) {
+            return Err(format!("missing {}: {}", Self::LOG_PREFIX, s));
+        }
+        let optional = |value: &str| (value != Self::NONE).then(|| value.to_owned());
+        let flag = |value: &str| {
+            value
+                .parse::<bool>()
+                .map_err(|_| format!("invalid boolean: {}", value))
+        };
+
+        let mut params = NegotiatedParams::default();
+        for field in fields {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("invalid parameter: {}", field))?;
+            match key {
+                "version" => params.protocol_version = Some(value.to_owned()),
+                "cipher_suite" => params.cipher_suite = Some(value.to_owned()),
+                "group" => params.key_exchange_group = Some(value.to_owned()),
+                "signature_scheme" => params.signature_scheme = Some(value.to_owned()),
+                "alpn" => params.alpn = Some(optional(value)),
+                "sni" => params.sni = Some(optional(value)),
+                "resumed" => params.resumed = Some(flag(value)?),
+                "hello_retry" => params.hello_retry = Some(flag(value)?),
+                _ => return Err(format!("unrecognized parameter: {}", key)),
+            }
+        }
+        Ok(params)
+    }
+}
+
+/// The performance of a peer on a single connection. Each field is `None` if
+/// the peer didn't measure it, e.g. because the TLS implementation doesn't
+/// expose key update counters.
+///
+/// Shims report the metrics of each connection by logging the [Display] form,
+/// which the runner parses out of the logs.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ScenarioMetrics {
+    /// From the start of the handshake until the handshake completed
+    pub handshake: Option<Duration>,
+    /// From the start of the handshake until the first byte of application
+    /// data was received
+    pub time_to_first_byte: Option<Duration>,
+    /// From the end of the handshake until all of the application data of the
+    /// scenario was exchanged
+    pub transfer: Option<Duration>,
+    /// The application data of the large data scenarios, which the throughput
+    /// is calculated from
+    pub data_bytes: Option<u64>,
+    pub key_updates_sent: Option<u64>,
+    pub key_updates_received: Option<u64>,
+}
+
+impl ScenarioMetrics {
+    /// Marks the log lines that contain metrics
+    pub const LOG_PREFIX: &'static str = "scenario_metrics";
+
+    /// Find the metrics of each connection in the output of a shim
+    pub fn from_log(log: &str) -> Vec<Result<Self, String>> {
+        log.lines()
+            .filter_map(|line| line.find(Self::LOG_PREFIX).map(|i| &line[i..]))
+            .map(str::parse)
+            .collect()
+    }
+
+    /// The throughput of the application data in bytes per second, which is
+    /// the same unit as `data_bytes`
+    pub fn throughput_bytes_per_s(&self) -> Option<f64> {
+        let (data_bytes, transfer) = (self.data_bytes?, self.transfer?);
+        let seconds = transfer.as_secs_f64();
+        (seconds > 0.0).then(|| data_bytes as f64 / seconds)
+    }
+
+    /// Durations are logged in milliseconds with microsecond precision
+    fn display_millis(duration: Duration) -> String {
+        let micros = duration.as_micros();
+        format!("{}.{:03}", micros / 1_000, micros % 1_000)
+    }
+
+    fn parse_millis(value: &str) -> Result<Duration, String> {
+        let invalid = || format!("invalid duration: {}", value);
+        let (millis, micros) = value.split_once('.').unwrap_or((value, "0"));
+        let millis: u64 = millis.parse().map_err(|_| invalid())?;
+        let micros: u64 = format!("{:0<3}", micros).parse().map_err(|_| invalid())?;
+        if micros >= 1_000 {
+            return Err(invalid());
+        }
+        Ok(Duration::from_micros(millis * 1_000 + micros))
+    }
+}
+
+/// Metrics are formatted as `scenario_metrics key=value key=value`, where
+/// metrics that weren't measured are omitted. The throughput is derived from
+/// the other metrics, so it is only logged for convenience.
+impl Display for ScenarioMetrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", Self::LOG_PREFIX)?;
+        let fields = [
+            ("handshake_ms", self.handshake.map(Self::display_millis)),
+            ("ttfb_ms", self.time_to_first_byte.map(Self::display_millis)),
+            ("transfer_ms", self.transfer.map(Self::display_millis)),
+            ("data_bytes", self.data_bytes.map(|v| v.to_string())),
+            (
+                "throughput_bytes_per_s",
+                self.throughput_bytes_per_s().map(|v| format!("{:.0}", v)),
+            ),
+            (
+                "key_updates_sent",
+                self.key_updates_sent.map(|v| v.to_string()),
+            ),
+            (
+                "key_updates_received",
+                self.key_updates_received.map(|v| v.to_string()),
+            ),
+        ];
+        for (key, value) in fields {
+            if let Some(value) = value {
+                write!(f, " {}={}", key, value)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for ScenarioMetrics {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut fields = s.split_whitespace();
+        if fields.next() != Some(Self::LOG_PREFIX) {
+            return Err(format!("missing {}: {}", Self::LOG_PREFIX, s));
+        }
+        let count = |value: &str| {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("invalid count: {}", value))
+        };
+
+        let mut metrics = ScenarioMetrics::default();
+        for field in fields {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| format!("invalid metric: {}", field))?;
+            match key {
+                "handshake_ms" => metrics.handshake = Some(Self::parse_millis(value)?),
+                "ttfb_ms" => metrics.time_to_first_byte = Some(Self::parse_millis(value)?),
+                "transfer_ms" => metrics.transfer = Some(Self::parse_millis(value)?),
+                "data_bytes" => metrics.data_bytes = Some(count(value)?),
+                "throughput_bytes_per_s" => {}
+                "key_updates_sent" => metrics.key_updates_sent = Some(count(value)?),
+                "key_updates_received" => metrics.key_updates_received = Some(count(value)?),
+                _ => return Err(format!("unrecognized metric: {}", key)),
+            }
+        }
+        Ok(metrics)
+    }
+}
+
+/// A TLS alert description. Descriptions are displayed with the names from
+/// RFC 8446 and RFC 5246, e.g. `unknown_ca`, or as a number if the description
+/// is unknown.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct AlertDescription(pub u8);
+
+impl AlertDescription {
+    pub const CLOSE_NOTIFY: Self = Self(0);
+    pub const UNEXPECTED_MESSAGE: Self = Self(10);
+    pub const HANDSHAKE_FAILURE: Self = Self(40);
+    pub const BAD_CERTIFICATE: Self = Self(42);
+    pub const CERTIFICATE_EXPIRED: Self = Self(45);
+    pub const CERTIFICATE_UNKNOWN: Self = Self(46);
+    pub const UNKNOWN_CA: Self = Self(48);
+    pub const NO_RENEGOTIATION: Self = Self(100);
+    pub const CERTIFICATE_REQUIRED: Self = Self(116);
+    pub const NO_APPLICATION_PROTOCOL: Self = Self(120);
+
+    // https://www.rfc-editor.org/rfc/rfc8446#section-6, and no_renegotiation from
+    // https://www.rfc-editor.org/rfc/rfc5246#section-7.2
+    const NAMES: [(u8, &'static str); 28] = [
+        (0, "close_notify"),
+        (10, "unexpected_message"),
+        (20, "bad_record_mac"),
+        (22, "record_overflow"),
+        (40, "handshake_failure"),
+        (42, "bad_certificate"),
+        (43, "unsupported_certificate"),
+        (44, "certificate_revoked"),
+        (45, "certificate_expired"),
+        (46, "certificate_unknown"),
+        (47, "illegal_parameter"),
+        (48, "unknown_ca"),
+        (49, "access_denied"),
+        (50, "decode_error"),
+        (51, "decrypt_error"),
+        (70, "protocol_version"),
+        (71, "insufficient_security"),
+        (80, "internal_error"),
+        (86, "inappropriate_fallback"),
+        (90, "user_canceled"),
+        (100, "no_renegotiation"),
+        (109, "missing_extension"),
+        (110, "unsupported_extension"),
+        (112, "unrecognized_name"),
+        (113, "bad_certificate_status_response"),
+        (115, "unknown_psk_identity"),
+        (116, "certificate_required"),
+        (120, "no_application_protocol"),
+    ];
+
+    pub fn name(&self) -> Option<&'static str> {
+        Self::NAMES
+            .iter()
+            .find(|(code, _)| *code == self.0)
+            .map(|(_, name)| *name)
+    }
+}
+
+impl Display for AlertDescription {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+impl FromStr for AlertDescription {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(code) = s.parse() {
+            return Ok(Self(code));
+        }
+        Self::NAMES
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(s))
+            .map(|(code, _)| Self(*code))
+            .ok_or_else(|| format!("unrecognized alert: {}", s))
+    }
+}
+
+/// A TLS alert that a peer sent or received when its connection failed.
+///
+/// Shims report alerts by logging the [Display] form, e.g. `tls_alert
+/// sent=unknown_ca`, which the runner parses out of the logs to check the
+/// alerts of scenarios that are expected to fail.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TlsAlert {
+    Sent(AlertDescription),
+    Received(AlertDescription),
+}
+
+impl TlsAlert {
+    /// Marks the log lines that contain alerts
+    pub const LOG_PREFIX: &'static str = "tls_alert";
+
+    pub fn description(&self) -> AlertDescription {
+        match self {
+            TlsAlert::Sent(description) | TlsAlert::Received(description) => *description,
+        }
+    }
+
+    /// Find all of the alerts in the output of a shim
+    pub fn from_log(log: &str) -> Vec<Result<Self, String>> {
+        log.lines()
+            .filter_map(|line| line.find(Self::LOG_PREFIX).map(|i| &line[i..]))
+            .map(str::parse)
+            .collect()
+    }
+}
+
+impl Display for TlsAlert {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TlsAlert::Sent(description) => write!(f, "{} sent={}", Self::LOG_PREFIX, description),
+            TlsAlert::Received(description) => {
+                write!(f, "{} received={}", Self::LOG_PREFIX, description)
+            }
+        }
+    }
+}
+
+impl FromStr for TlsAlert {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid {}: {}", Self::LOG_PREFIX, s);
+        let alert = s
+            .strip_prefix(Self::LOG_PREFIX)
+            .and_then(|alert| alert.split_whitespace().next())
+            .ok_or_else(invalid)?;
+        match alert.split_once('=').ok_or_else(invalid)? {
+            ("sent", description) => Ok(TlsAlert::Sent(description.parse()?)),
+            ("received", description) => Ok(TlsAlert::Received(description.parse()?)),
+            _ => Err(invalid()),
+        }
+    }
 }
 
 /// This enum contains all of the defined Interop Test types. See the readme for more
@@ -84,8 +875,222 @@ pub enum InteropTest {
     Greeting,
     LargeDataDownload,
     LargeDataDownloadWithFrequentKeyUpdates,
+    LargeDataUpload,
+    LargeDataUploadWithFrequentKeyUpdates,
     MTLSRequestResponse,
     SessionResumption,
+    HelloRetryRequest,
+    SmallTcpPacket,
+    KeyUpdateRequest,
+    KeyUpdateFlood,
+    AlpnMatch,
+    AlpnServerPreference,
+    AlpnNoOverlap,
+    AlpnServerUnused,
+    SniSelection,
+    SniAbsent,
+    UntrustedServerCa,
+    HostnameMismatch,
+    ExpiredServerCert,
+    MTLSMissingClientCert,
+    MTLSUntrustedClientCert,
+    SessionIdResumption,
+    RenegotiationRefusal,
+    PqHandshake,
+}
+
+impl InteropTest {
+    /// The ALPN protocols that the client offers, in order of preference
+    pub fn client_alpn_protocols(&self) -> &'static [&'static str] {
+        match self {
+            InteropTest::AlpnMatch | InteropTest::AlpnServerUnused => &["h2", "http/1.1"],
+            InteropTest::AlpnServerPreference => &["http/1.1", "h2"],
+            InteropTest::AlpnNoOverlap => &["h2"],
+            _ => &[],
+        }
+    }
+
+    /// The ALPN protocols that the server supports, in order of preference
+    pub fn server_alpn_protocols(&self) -> &'static [&'static str] {
+        match self {
+            InteropTest::AlpnMatch | InteropTest::AlpnServerPreference => &["h2", "http/1.1"],
+            InteropTest::AlpnNoOverlap => &["http/1.1"],
+            _ => &[],
+        }
+    }
+
+    /// The ALPN protocol that both peers are expected to select. Servers select
+    /// the protocol using their own preference order.
+    pub fn expected_alpn_protocol(&self) -> Option<&'static str> {
+        match self {
+            InteropTest::AlpnMatch | InteropTest::AlpnServerPreference => Some("h2"),
+            _ => None,
+        }
+    }
+
+    /// The server name that the client sends in the SNI extension, or `None` if
+    /// the client doesn't send SNI.
+    pub fn server_name(&self) -> Option<&'static str> {
+        match self {
+            InteropTest::SniSelection => Some(ALTERNATE_SERVER_NAME),
+            InteropTest::SniAbsent => None,
+            _ => Some(SERVER_NAME),
+        }
+    }
+
+    /// The key exchange group that both peers must negotiate, or `None` if the
+    /// peers may use their default groups
+    pub fn required_group(&self) -> Option<KeyExchangeGroup> {
+        match self {
+            InteropTest::PqHandshake => Some(KeyExchangeGroup::X25519Mlkem768),
+            _ => None,
+        }
+    }
+
+    /// Returns true if the server should hold the alternate certificate in
+    /// addition to the default certificate, and select between them using SNI.
+    pub fn uses_alternate_certificate(&self) -> bool {
+        matches!(self, InteropTest::SniSelection | InteropTest::SniAbsent)
+    }
+
+    /// The certificate chain and private key that the server presents
+    pub fn server_certificate(&self) -> (PemType, PemType) {
+        match self {
+            InteropTest::HostnameMismatch => {
+                (PemType::AlternateServerChain, PemType::AlternateServerKey)
+            }
+            InteropTest::ExpiredServerCert => {
+                (PemType::ExpiredServerChain, PemType::ExpiredServerKey)
+            }
+            InteropTest::UntrustedServerCa => {
+                (PemType::UntrustedServerChain, PemType::UntrustedServerKey)
+            }
+            _ => (PemType::ServerChain, PemType::ServerKey),
+        }
+    }
+
+    /// The certificate chain and private key that the client presents, or `None`
+    /// if the client doesn't have a certificate
+    pub fn client_certificate(&self) -> Option<(PemType, PemType)> {
+        match self {
+            InteropTest::MTLSRequestResponse => Some((PemType::ClientChain, PemType::ClientKey)),
+            InteropTest::MTLSUntrustedClientCert => {
+                Some((PemType::UntrustedClientChain, PemType::UntrustedClientKey))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns true if the server must authenticate the client
+    pub fn requires_client_auth(&self) -> bool {
+        matches!(
+            self,
+            InteropTest::MTLSRequestResponse
+                | InteropTest::MTLSMissingClientCert
+                | InteropTest::MTLSUntrustedClientCert
+        )
+    }
+
+    /// Returns true if the scenario applies to `version`. The key update,
+    /// HelloRetryRequest and hybrid key exchange scenarios only exist in TLS 1.3,
+    /// while session ID resumption and renegotiation only exist in TLS 1.2.
+    pub fn supports_tls_version(&self, version: TlsVersion) -> bool {
+        match self {
+            InteropTest::HelloRetryRequest
+            | InteropTest::KeyUpdateRequest
+            | InteropTest::KeyUpdateFlood
+            | InteropTest::LargeDataDownloadWithFrequentKeyUpdates
+            | InteropTest::LargeDataUploadWithFrequentKeyUpdates
+            | InteropTest::PqHandshake => version == TlsVersion::Tls13,
+            InteropTest::SessionIdResumption | InteropTest::RenegotiationRefusal => {
+                version == TlsVersion::Tls12
+            }
+            _ => true,
+        }
+    }
+
+    /// Returns true if the scenario transfers the configured [DataVolume]
+    pub fn transfers_data_volume(&self) -> bool {
+        matches!(
+            self,
+            InteropTest::LargeDataDownload
+                | InteropTest::LargeDataDownloadWithFrequentKeyUpdates
+                | InteropTest::LargeDataUpload
+                | InteropTest::LargeDataUploadWithFrequentKeyUpdates
+                | InteropTest::KeyUpdateFlood
+        )
+    }
+
+    /// Returns true if the client makes a second connection, which the server
+    /// must resume from the session of the first connection.
+    pub fn resumes_session(&self) -> bool {
+        matches!(
+            self,
+            InteropTest::SessionResumption | InteropTest::SessionIdResumption
+        )
+    }
+
+    /// The alerts that are acceptable when the connection fails in a scenario
+    /// that is expected to fail. RFC 8446 prescribes a specific alert for most
+    /// failures, but it doesn't specify one for a certificate that isn't valid
+    /// for the server name. An untrusted certificate is also commonly rejected
+    /// with certificate_unknown, e.g. by the JDK and s2n-tls.
+    pub fn expected_alerts(&self) -> &'static [AlertDescription] {
+        match self {
+            InteropTest::AlpnNoOverlap => &[AlertDescription::NO_APPLICATION_PROTOCOL],
+            InteropTest::UntrustedServerCa | InteropTest::MTLSUntrustedClientCert => &[
+                AlertDescription::UNKNOWN_CA,
+                AlertDescription::CERTIFICATE_UNKNOWN,
+            ],
+            InteropTest::HostnameMismatch => &[
+                AlertDescription::BAD_CERTIFICATE,
+                AlertDescription::CERTIFICATE_UNKNOWN,
+            ],
+            InteropTest::ExpiredServerCert => &[AlertDescription::CERTIFICATE_EXPIRED],
+            InteropTest::MTLSMissingClientCert => &[AlertDescription::CERTIFICATE_REQUIRED],
+            // servers either refuse with a no_renegotiation warning, which
+            // clients that requested the renegotiation treat as a handshake
+            // failure, or reject the ClientHello as an unexpected message
+            InteropTest::RenegotiationRefusal => &[
+                AlertDescription::NO_RENEGOTIATION,
+                AlertDescription::HANDSHAKE_FAILURE,
+                AlertDescription::UNEXPECTED_MESSAGE,
+            ],
+            _ => &[],
+        }
+    }
+
+    /// Returns true if the client is the peer that rejects the connection, and
+    /// so sends the alert, in a scenario that is expected to fail.
+    pub fn client_sends_alert(&self) -> bool {
+        matches!(
+            self,
+            InteropTest::UntrustedServerCa
+                | InteropTest::HostnameMismatch
+                | InteropTest::ExpiredServerCert
+        )
+    }
+
+    /// Returns true if the handshake is expected to fail. Clients and servers
+    /// should log [EXPECTED_FAILURE_LOG] and exit successfully if the connection
+    /// fails for these scenarios.
+    ///
+    /// In TLS 1.3 the client finishes the handshake before the server
+    /// authenticates it, so a client may only observe the failure when it reads
+    /// the server's alert after the handshake. Similarly the renegotiation
+    /// refusal scenario fails the renegotiation after the initial handshake.
+    pub fn expects_handshake_failure(&self) -> bool {
+        matches!(
+            self,
+            InteropTest::AlpnNoOverlap
+                | InteropTest::UntrustedServerCa
+                | InteropTest::HostnameMismatch
+                | InteropTest::ExpiredServerCert
+                | InteropTest::MTLSMissingClientCert
+                | InteropTest::MTLSUntrustedClientCert
+                | InteropTest::RenegotiationRefusal
+        )
+    }
 }
 
 impl FromStr for InteropTest {
@@ -99,8 +1104,30 @@ impl FromStr for InteropTest {
             "large_data_download_with_frequent_key_updates" => {
                 InteropTest::LargeDataDownloadWithFrequentKeyUpdates
             }
+            "large_data_upload" => InteropTest::LargeDataUpload,
+            "large_data_upload_with_frequent_key_updates" => {
+                InteropTest::LargeDataUploadWithFrequentKeyUpdates
+            }
             "mtls_request_response" => InteropTest::MTLSRequestResponse,
             "session_resumption" => InteropTest::SessionResumption,
+            "hello_retry_request" => InteropTest::HelloRetryRequest,
+            "small_tcp_packet" => InteropTest::SmallTcpPacket,
+            "key_update_request" => InteropTest::KeyUpdateRequest,
+            "key_update_flood" => InteropTest::KeyUpdateFlood,
+            "alpn_match" => InteropTest::AlpnMatch,
+            "alpn_server_preference" => InteropTest::AlpnServerPreference,
+            "alpn_no_overlap" => InteropTest::AlpnNoOverlap,
+            "alpn_server_unused" => InteropTest::AlpnServerUnused,
+            "sni_selection" => InteropTest::SniSelection,
+            "sni_absent" => InteropTest::SniAbsent,
+            "untrusted_server_ca" => InteropTest::UntrustedServerCa,
+            "hostname_mismatch" => InteropTest::HostnameMismatch,
+            "expired_server_cert" => InteropTest::ExpiredServerCert,
+            "mtls_missing_client_cert" => InteropTest::MTLSMissingClientCert,
+            "mtls_untrusted_client_cert" => InteropTest::MTLSUntrustedClientCert,
+            "session_id_resumption" => InteropTest::SessionIdResumption,
+            "renegotiation_refusal" => InteropTest::RenegotiationRefusal,
+            "pq_handshake" => InteropTest::PqHandshake,
             _ => return Err(format!("unrecognized test type: {}", s)),
         };
         Ok(name)
@@ -116,8 +1143,30 @@ impl Display for InteropTest {
             InteropTest::LargeDataDownloadWithFrequentKeyUpdates => {
                 "large_data_download_with_frequent_key_updates"
             },
+            InteropTest::LargeDataUpload => "large_data_upload",
+            InteropTest::LargeDataUploadWithFrequentKeyUpdates => {
+                "large_data_upload_with_frequent_key_updates"
+            },
             InteropTest::MTLSRequestResponse => "mtls_request_response",
             InteropTest::SessionResumption => "session_resumption",
+            InteropTest::HelloRetryRequest => "hello_retry_request",
+            InteropTest::SmallTcpPacket => "small_tcp_packet",
+            InteropTest::KeyUpdateRequest => "key_update_request",
+            InteropTest::KeyUpdateFlood => "key_update_flood",
+            InteropTest::AlpnMatch => "alpn_match",
+            InteropTest::AlpnServerPreference => "alpn_server_preference",
+            InteropTest::AlpnNoOverlap => "alpn_no_overlap",
+            InteropTest::AlpnServerUnused => "alpn_server_unused",
+            InteropTest::SniSelection => "sni_selection",
+            InteropTest::SniAbsent => "sni_absent",
+            InteropTest::UntrustedServerCa => "untrusted_server_ca",
+            InteropTest::HostnameMismatch => "hostname_mismatch",
+            InteropTest::ExpiredServerCert => "expired_server_cert",
+            InteropTest::MTLSMissingClientCert => "mtls_missing_client_cert",
+            InteropTest::MTLSUntrustedClientCert => "mtls_untrusted_client_cert",
+            InteropTest::SessionIdResumption => "session_id_resumption",
+            InteropTest::RenegotiationRefusal => "renegotiation_refusal",
+            InteropTest::PqHandshake => "pq_handshake",
         };
         write!(f, "{}", name)
     }
@@ -132,5 +1181,156 @@ mod tests {
         std::fs::read(pem_file_path(PemType::CaCert)).unwrap();
         std::fs::read(pem_file_path(PemType::ServerChain)).unwrap();
         std::fs::read(pem_file_path(PemType::ServerKey)).unwrap();
+        std::fs::read(pem_file_path(PemType::AlternateServerChain)).unwrap();
+        std::fs::read(pem_file_path(PemType::AlternateServerKey)).unwrap();
+        std::fs::read(pem_file_path(PemType::ExpiredServerChain)).unwrap();
+        std::fs::read(pem_file_path(PemType::ExpiredServerKey)).unwrap();
+        std::fs::read(pem_file_path(PemType::UntrustedServerChain)).unwrap();
+        std::fs::read(pem_file_path(PemType::UntrustedServerKey)).unwrap();
+        std::fs::read(pem_file_path(PemType::UntrustedClientChain)).unwrap();
+        std::fs::read(pem_file_path(PemType::UntrustedClientKey)).unwrap();
+    }
+
+    #[test]
+    fn exit_code_round_trip() {
+        for exit_code in ExitCode::ALL {
+            assert_eq!(ExitCode::from_code(exit_code.code()), Some(exit_code));
+        }
+        assert_eq!(ExitCode::Unimplemented.code(), 127);
+        // a rust panic isn't part of the contract
+        assert_eq!(ExitCode::from_code(101), None);
+    }
+
+    #[test]
+    fn data_volume_round_trip() {
+        for volume in [
+            DataVolume::QUICK,
+            DataVolume::FULL,
+            DataVolume {
+                blocks: 4,
+                mb_per_block: 250,
+            },
+        ] {
+            assert_eq!(volume.to_string().parse::<DataVolume>().unwrap(), volume);
+        }
+        assert_eq!("8x1".parse::<DataVolume>().unwrap(), DataVolume::QUICK);
+        assert!("8".parse::<DataVolume>().is_err());
+    }
+
+    #[test]
+    fn negotiated_params_round_trip() {
+        let params = NegotiatedParams {
+            protocol_version: Some("TLSv1.3".to_owned()),
+            cipher_suite: Some("TLS_AES_128_GCM_SHA256".to_owned()),
+            alpn: Some(None),
+            sni: Some(Some("localhost".to_owned())),
+            hello_retry: Some(false),
+            ..Default::default()
+        };
+        let log = format!("2024-05-21T00:00:00Z  INFO shim: {}\n", params);
+        assert_eq!(NegotiatedParams::from_log(&log), Some(Ok(params.clone())));
+        assert_eq!(NegotiatedParams::from_log("no parameters"), None);
+
+        // parameters that only one peer reported aren't compared
+        let peer = NegotiatedParams {
+            cipher_suite: Some("tls_aes_128_gcm_sha256".to_owned()),
+            alpn: Some(Some("h2".to_owned())),
+            resumed: Some(false),
+            ..Default::default()
+        };
+        assert_eq!(params.disagreements(&peer), vec!["alpn: - != h2"]);
+    }
+
+    #[test]
+    fn scenario_metrics_round_trip() {
+        let metrics = [
+            ScenarioMetrics {
+                handshake: Some(Duration::from_micros(2_345)),
+                time_to_first_byte: Some(Duration::from_micros(3_001)),
+                transfer: Some(Duration::from_millis(500)),
+                data_bytes: Some(8_000_000),
+                key_updates_sent: Some(8),
+                key_updates_received: Some(0),
+            },
+            ScenarioMetrics {
+                handshake: Some(Duration::from_secs(1)),
+                ..Default::default()
+            },
+        ];
+        assert_eq!(metrics[0].throughput_bytes_per_s(), Some(16_000_000.0));
+        assert!(metrics[0]
+            .to_string()
+            .contains(" throughput_bytes_per_s=16000000 "));
+        let log: String = metrics
+            .iter()
+            .map(|m| format!("2024-05-21T00:00:00Z  INFO shim: {}\n", m))
+            .collect();
+        let parsed: Vec<_> = metrics.iter().cloned().map(Ok).collect();
+        assert_eq!(ScenarioMetrics::from_log(&log), parsed);
+        assert_eq!(
+            "scenario_metrics ttfb_ms=1.5".parse(),
+            Ok(ScenarioMetrics {
+                time_to_first_byte: Some(Duration::from_micros(1_500)),
+                ..Default::default()
+            })
+        );
+        assert!("scenario_metrics ttfb_ms=1.5000"
+            .parse::<ScenarioMetrics>()
+            .is_err());
+    }
+
+    #[test]
+    fn tls_alert_round_trip() {
+        let alerts = [
+            TlsAlert::Sent(AlertDescription::UNKNOWN_CA),
+            TlsAlert::Received(AlertDescription::CERTIFICATE_REQUIRED),
+            TlsAlert::Received(AlertDescription(255)),
+        ];
+        let log: String = alerts
+            .iter()
+            .map(|alert| format!("2024-05-21T00:00:00Z  INFO shim: {}\n", alert))
+            .collect();
+        let parsed: Vec<_> = alerts.iter().copied().map(Ok).collect();
+        assert_eq!(TlsAlert::from_log(&log), parsed);
+        assert_eq!(
+            "tls_alert received=certificate_expired".parse(),
+            Ok(TlsAlert::Received(AlertDescription::CERTIFICATE_EXPIRED))
+        );
+        assert!("tls_alert dropped=unknown_ca".parse::<TlsAlert>().is_err());
+        assert!("tls_alert sent=not_an_alert".parse::<TlsAlert>().is_err());
+    }
+
+    #[test]
+    fn parameters_round_trip() {
+        let parameters = TestParameters {
+            data_volume: DataVolume::QUICK,
+            tls_version: TlsVersion::Tls12,
+            cipher_suite: Some(CipherSuite::Chacha20Poly1305Sha256),
+            group: Some(KeyExchangeGroup::Secp384r1),
+        };
+        let parsed = TestParameters::default()
+            .with_flags(&parameters.to_args())
+            .unwrap();
+        assert_eq!(parsed, parameters);
+        // unpinned algorithms aren't passed to the shims
+        assert_eq!(TestParameters::default().to_args().len(), 4);
+        assert!(TestParameters::default()
+            .with_flags(&["--data-volume".to_owned()])
+            .is_err());
+        assert!(TestParameters::default()
+            .with_flags(&["--tls-version".to_owned(), "tls1.1".to_owned()])
+            .is_err());
+        assert!(TestParameters::default()
+            .with_flags(&["--group".to_owned(), "x448".to_owned()])
+            .is_err());
+        // the group that a scenario requires takes precedence
+        assert_eq!(
+            parameters.group_for(InteropTest::PqHandshake),
+            Some(KeyExchangeGroup::X25519Mlkem768)
+        );
+        assert_eq!(
+            parameters.group_for(InteropTest::Greeting),
+            Some(KeyExchangeGroup::Secp384r1)
+        );
     }
 }
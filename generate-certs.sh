@@ -10,6 +10,15 @@
 # immediately bail if any command fails
 set -e
 
+# the expired certificate is backdated with the -not_before and -not_after
+# options of `openssl x509`, which were added in OpenSSL 3.4
+openssl_version=$(openssl version)
+if [[ ! $openssl_version =~ ^OpenSSL\ ([0-9]+)\.([0-9]+) ]] ||
+   (( BASH_REMATCH[1] < 3 || (BASH_REMATCH[1] == 3 && BASH_REMATCH[2] < 4) )); then
+    echo "OpenSSL 3.4 or later is required, found: $openssl_version" >&2
+    exit 1
+fi
+
 # Generates certs with given algorithms and bits in $1$2/, ex. ec384/
 # $1: rsa or ec
 # $2: number of bits
@@ -87,6 +96,28 @@ cert-gen () {
             -subj "/C=US/CN=leaf" \
             -addext "subjectAltName = DNS:localhost"
 
+    # the alternate server certificate is selected by servers when the client
+    # requests the alternate name through SNI
+    echo "generating alternate server private key and CSR"
+    openssl req  -new -noenc \
+            -newkey $key_family \
+            -pkeyopt $argname$key_size \
+            -keyout alternate-server-key.pem \
+            -out alternate-server.csr \
+            -subj "/C=US/CN=alternate" \
+            -addext "subjectAltName = DNS:alternate.localhost"
+
+    # the expired server certificate is signed by the trusted intermediate, but
+    # its validity period ended long ago
+    echo "generating expired server private key and CSR"
+    openssl req  -new -noenc \
+            -newkey $key_family \
+            -pkeyopt $argname$key_size \
+            -keyout expired-server-key.pem \
+            -out expired-server.csr \
+            -subj "/C=US/CN=expired" \
+            -addext "subjectAltName = DNS:localhost"
+
     echo "generating client private key and CSR"
     openssl req  -new -noenc \
             -newkey $key_family \
@@ -117,6 +148,26 @@ cert-gen () {
             -CAcreateserial -out server-cert.pem \
             -copy_extensions=copyall
 
+    echo "generating alternate server certificate and signing it"
+    openssl x509 -days 65536 \
+            -req -in alternate-server.csr \
+            $signature_options \
+            -$digest \
+            -CA intermediate-cert.pem \
+            -CAkey intermediate-key.pem \
+            -CAcreateserial -out alternate-server-cert.pem \
+            -copy_extensions=copyall
+
+    echo "generating expired server certificate and signing it"
+    openssl x509 -not_before 20000101000000Z -not_after 20010101000000Z \
+            -req -in expired-server.csr \
+            $signature_options \
+            -$digest \
+            -CA intermediate-cert.pem \
+            -CAkey intermediate-key.pem \
+            -CAcreateserial -out expired-server-cert.pem \
+            -copy_extensions=copyall
+
     echo "generating client certificate and signing it"
     openssl x509 -days 65536 \
             -req -in client.csr \
@@ -132,31 +183,113 @@ cert-gen () {
     cat intermediate-cert.pem >> server-chain.pem
     cat ca-cert.pem >> server-chain.pem
 
+    touch alternate-server-chain.pem
+    cat alternate-server-cert.pem >> alternate-server-chain.pem
+    cat intermediate-cert.pem >> alternate-server-chain.pem
+    cat ca-cert.pem >> alternate-server-chain.pem
+
+    touch expired-server-chain.pem
+    cat expired-server-cert.pem >> expired-server-chain.pem
+    cat intermediate-cert.pem >> expired-server-chain.pem
+    cat ca-cert.pem >> expired-server-chain.pem
+
+    # the untrusted certificates are issued by a separate root that peers don't
+    # trust, and are otherwise identical to the trusted certificates
+    echo "generating untrusted CA private key and certificate"
+    openssl req -new -noenc -x509 \
+            -newkey $key_family \
+            -pkeyopt $argname$key_size \
+            -keyout untrusted-ca-key.pem \
+            -out untrusted-ca-cert.pem \
+            -days 65536 \
+            $signature_options \
+            -$digest \
+            -subj "/C=US/CN=untrusted root" \
+            -addext "basicConstraints = critical,CA:true" \
+            -addext "keyUsage = critical,keyCertSign"
+
+    echo "generating untrusted server private key and CSR"
+    openssl req  -new -noenc \
+            -newkey $key_family \
+            -pkeyopt $argname$key_size \
+            -keyout untrusted-server-key.pem \
+            -out untrusted-server.csr \
+            -subj "/C=US/CN=untrusted leaf" \
+            -addext "subjectAltName = DNS:localhost"
+
+    echo "generating untrusted client private key and CSR"
+    openssl req  -new -noenc \
+            -newkey $key_family \
+            -pkeyopt $argname$key_size \
+            -keyout untrusted-client-key.pem \
+            -out untrusted-client.csr \
+            -subj "/C=US/CN=untrusted client" \
+            -addext "subjectAltName = DNS:localhost"
+
+    echo "generating untrusted server certificate and signing it"
+    openssl x509 -days 65536 \
+            -req -in untrusted-server.csr \
+            $signature_options \
+            -$digest \
+            -CA untrusted-ca-cert.pem \
+            -CAkey untrusted-ca-key.pem \
+            -CAcreateserial -out untrusted-server-cert.pem \
+            -copy_extensions=copyall
+
+    echo "generating untrusted client certificate and signing it"
+    openssl x509 -days 65536 \
+            -req -in untrusted-client.csr \
+            $signature_options \
+            -$digest \
+            -CA untrusted-ca-cert.pem \
+            -CAkey untrusted-ca-key.pem \
+            -CAcreateserial -out untrusted-client-cert.pem \
+            -copy_extensions=copyall
+
+    touch untrusted-server-chain.pem
+    cat untrusted-server-cert.pem >> untrusted-server-chain.pem
+    cat untrusted-ca-cert.pem >> untrusted-server-chain.pem
+
     echo "verifying server certificates"
     openssl verify -CAfile ca-cert.pem intermediate-cert.pem
     openssl verify -CAfile ca-cert.pem -untrusted intermediate-cert.pem server-cert.pem
+    openssl verify -CAfile ca-cert.pem -untrusted intermediate-cert.pem alternate-server-cert.pem
 
     echo "verifying client certificates"
     openssl verify -CAfile ca-cert.pem client-cert.pem
 
+    echo "verifying untrusted certificates"
+    openssl verify -CAfile untrusted-ca-cert.pem untrusted-server-cert.pem
+    openssl verify -CAfile untrusted-ca-cert.pem untrusted-client-cert.pem
+
     # certificate signing requests are never used after the certs are generated
     rm server.csr
+    rm alternate-server.csr
+    rm expired-server.csr
+    rm untrusted-server.csr
+    rm untrusted-client.csr
     rm intermediate.csr
     rm client.csr
 
     # serial files are generated during the signing process, but are not used
     rm ca-cert.srl
     rm intermediate-cert.srl
+    rm untrusted-ca-cert.srl
 
     # the private keys of the CA and the intermediat CA are never needed after 
     # signing
     rm ca-key.pem
     rm intermediate-key.pem
+    rm untrusted-ca-key.pem
 
     # the intermediate and server certs are included in server-chain.pem, so 
     # the individual files can be deleted
     rm intermediate-cert.pem
     rm server-cert.pem
+    rm alternate-server-cert.pem
+    rm expired-server-cert.pem
+    rm untrusted-server-cert.pem
+    rm untrusted-ca-cert.pem
 
     cd ..
 }
@@ -4,32 +4,265 @@ import (
 	"bufio"
 	"crypto/tls"
 	"crypto/x509"
+	"errors"
+	"flag"
 	"fmt"
 	"io"
+	"net"
 	"os"
+	"reflect"
+	"time"
 )
 
 const (
-	LargeDataDownloadGB = 256
-	ClientGreeting      = "i am the client. nice to meet you server."
-	ServerGreeting      = "i am the server. a pleasure to make your acquaintance."
-	Host                = "localhost"
+	ClientGreeting = "i am the client. nice to meet you server."
+	ServerGreeting = "i am the server. a pleasure to make your acquaintance."
+	Host           = "localhost"
+
+	// AlternateServerName is requested through SNI in the sni_selection scenario
+	AlternateServerName = "alternate.localhost"
+)
+
+// Exit codes mirror the ExitCode definition in the rust `common` crate.
+// Invalid arguments and configuration errors are reported as an assertion
+// failure.
+const (
+	ExitSuccess          = 0
+	ExitAssertionFailure = 1
+	ExitHandshakeFailure = 2
+	ExitIoError          = 3
+	ExitTimeout          = 4
+	ExitUnimplemented    = 127
+)
+
+// alpnScenario mirrors the ALPN configuration of the InteropTest in the rust
+// `common` crate. An empty expected protocol indicates that no protocol should
+// be selected.
+type alpnScenario struct {
+	offered  []string
+	expected string
+}
+
+var alpnScenarios = map[string]alpnScenario{
+	"alpn_match":             {[]string{"h2", "http/1.1"}, "h2"},
+	"alpn_server_preference": {[]string{"http/1.1", "h2"}, "h2"},
+	"alpn_no_overlap":        {[]string{"h2"}, ""},
+	"alpn_server_unused":     {[]string{"h2", "http/1.1"}, ""},
+}
+
+// expectedFailures mirrors InteropTest::expects_handshake_failure in the rust
+// `common` crate
+var expectedFailures = map[string]bool{
+	"alpn_no_overlap":            true,
+	"untrusted_server_ca":        true,
+	"hostname_mismatch":          true,
+	"expired_server_cert":        true,
+	"mtls_missing_client_cert":   true,
+	"mtls_untrusted_client_cert": true,
+}
+
+// clientCertificates holds the certificate and key that the client presents in
+// the scenarios that use client authentication
+var clientCertificates = map[string][2]string{
+	"mtls_request_response":      {"../certificates/client-cert.pem", "../certificates/client-key.pem"},
+	"mtls_untrusted_client_cert": {"../certificates/untrusted-client-cert.pem", "../certificates/untrusted-client-key.pem"},
+}
+
+// DataVolume mirrors the DataVolume definition in the rust `common` crate.
+// Large data is sent in blocks of 1 MB writes, and the first byte of each MB
+// is the index of its block (mod 255).
+type DataVolume struct {
+	Blocks     int
+	MbPerBlock int
+}
+
+// BytesPerMB is the number of bytes in a MB
+const BytesPerMB = 1_000_000
+
+func (v DataVolume) TotalBytes() int {
+	return v.Blocks * v.MbPerBlock * BytesPerMB
+}
+
+var (
+	QuickDataVolume = DataVolume{Blocks: 8, MbPerBlock: 1}
+	FullDataVolume  = DataVolume{Blocks: 256, MbPerBlock: 1_000}
 )
 
+func parseDataVolume(s string) (DataVolume, error) {
+	switch s {
+	case "quick":
+		return QuickDataVolume, nil
+	case "full":
+		return FullDataVolume, nil
+	}
+	var volume DataVolume
+	_, err := fmt.Sscanf(s, "%dx%d", &volume.Blocks, &volume.MbPerBlock)
+	return volume, err
+}
+
+// groups mirrors KeyExchangeGroup in the rust `common` crate. The hybrid group
+// requires Go 1.24+.
+var groups = map[string]tls.CurveID{
+	"x25519":         tls.X25519,
+	"secp256r1":      tls.CurveP256,
+	"secp384r1":      tls.CurveP384,
+	"X25519MLKEM768": tls.X25519MLKEM768,
+}
+
+// parseTlsVersion mirrors TlsVersion in the rust `common` crate
+func parseTlsVersion(s string) (uint16, error) {
+	switch s {
+	case "tls1.2":
+		return tls.VersionTLS12, nil
+	case "tls1.3":
+		return tls.VersionTLS13, nil
+	}
+	return 0, fmt.Errorf("unrecognized tls version: %s", s)
+}
+
+// printNegotiatedParams reports the negotiated parameters in the format that
+// is parsed by the runner. See NegotiatedParams in the rust `common` crate.
+func printNegotiatedParams(state tls.ConnectionState) {
+	version := fmt.Sprintf("0x%04x", state.Version)
+	switch state.Version {
+	case tls.VersionTLS13:
+		version = "TLSv1.3"
+	case tls.VersionTLS12:
+		version = "TLSv1.2"
+	}
+	alpn := state.NegotiatedProtocol
+	if alpn == "" {
+		alpn = "-"
+	}
+	sni := state.ServerName
+	if sni == "" {
+		sni = "-"
+	}
+	fmt.Printf("negotiated_params version=%s cipher_suite=%s alpn=%s sni=%s resumed=%t\n",
+		version, tls.CipherSuiteName(state.CipherSuite), alpn, sni, state.DidResume)
+}
+
+// firstByteReader records when the first byte of application data was read
+type firstByteReader struct {
+	reader    io.Reader
+	firstByte time.Time
+}
+
+func (r *firstByteReader) Read(p []byte) (int, error) {
+	n, err := r.reader.Read(p)
+	if n > 0 && r.firstByte.IsZero() {
+		r.firstByte = time.Now()
+	}
+	return n, err
+}
+
+// formatMillis formats a duration in milliseconds with microsecond precision
+func formatMillis(d time.Duration) string {
+	micros := d.Microseconds()
+	return fmt.Sprintf("%d.%03d", micros/1_000, micros%1_000)
+}
+
+// printScenarioMetrics reports the metrics of the connection in the format
+// that is parsed by the runner. See ScenarioMetrics in the rust `common`
+// crate. crypto/tls doesn't expose key update counters, so they are omitted,
+// and dataBytes is 0 for scenarios that don't transfer the data volume.
+func printScenarioMetrics(start time.Time, handshake time.Duration, firstByte time.Time, transfer time.Duration, dataBytes int) {
+	metrics := "scenario_metrics handshake_ms=" + formatMillis(handshake)
+	if !firstByte.IsZero() {
+		metrics += " ttfb_ms=" + formatMillis(firstByte.Sub(start))
+	}
+	metrics += " transfer_ms=" + formatMillis(transfer)
+	if dataBytes > 0 {
+		metrics += fmt.Sprintf(" data_bytes=%d", dataBytes)
+		if transfer > 0 {
+			metrics += fmt.Sprintf(" throughput_bytes_per_s=%.0f", float64(dataBytes)/transfer.Seconds())
+		}
+	}
+	fmt.Println(metrics)
+}
+
+// printReceivedAlert reports an alert that was received from the server in the
+// format that is parsed by the runner. See TlsAlert in the rust `common` crate.
+// crypto/tls doesn't export its alert type, but a received alert is returned
+// as a "remote error" that wraps the alert description.
+func printReceivedAlert(err error) {
+	var opErr *net.OpError
+	if !errors.As(err, &opErr) || opErr.Op != "remote error" {
+		return
+	}
+	if alert := reflect.ValueOf(opErr.Err); alert.Kind() == reflect.Uint8 {
+		fmt.Printf("tls_alert received=%d\n", alert.Uint())
+	}
+}
+
+// exitWithError reports an error that occurred after the handshake, and exits
+// with the code that classifies it. Like the rust shims, a connection that was
+// closed with an alert is a handshake failure.
+func exitWithError(message string, err error) {
+	fmt.Println(message, err)
+	var netErr net.Error
+	var opErr *net.OpError
+	switch {
+	case errors.As(err, &netErr) && netErr.Timeout():
+		os.Exit(ExitTimeout)
+	case errors.As(err, &opErr) && opErr.Op == "remote error":
+		os.Exit(ExitHandshakeFailure)
+	default:
+		os.Exit(ExitIoError)
+	}
+}
+
+// exitWithHandshakeError reports an error from dialing the server, which is an
+// i/o error if the TCP connection couldn't be established.
+func exitWithHandshakeError(message string, err error) {
+	fmt.Println(message, err)
+	var netErr net.Error
+	var opErr *net.OpError
+	switch {
+	case errors.As(err, &netErr) && netErr.Timeout():
+		os.Exit(ExitTimeout)
+	case errors.As(err, &opErr) && opErr.Op == "dial":
+		os.Exit(ExitIoError)
+	default:
+		os.Exit(ExitHandshakeFailure)
+	}
+}
+
 func main() {
 	// Parse the test arguments
 	if len(os.Args) < 3 {
-		fmt.Println("Usage: go run main.go <test_case> <port>")
-		return
+		fmt.Println("Usage: go run main.go <test_case> <port> [--data-volume <volume>] [--tls-version <version>] [--cipher-suite <suite>] [--group <group>]")
+		os.Exit(ExitAssertionFailure)
 	}
 	testCase := os.Args[1]
 	port := os.Args[2]
 
-	// Load client certificate and key
-	clientCert, err := tls.LoadX509KeyPair("../certificates/client-cert.pem", "../certificates/client-key.pem")
+	flags := flag.NewFlagSet("client", flag.ContinueOnError)
+	dataVolumeFlag := flags.String("data-volume", "full", "amount of data transferred by the large data scenarios")
+	tlsVersionFlag := flags.String("tls-version", "tls1.3", "the only protocol version that is enabled")
+	cipherSuiteFlag := flags.String("cipher-suite", "", "the only TLS 1.3 cipher suite that is enabled")
+	groupFlag := flags.String("group", "", "the only key exchange group that is enabled")
+	if err := flags.Parse(os.Args[3:]); err != nil {
+		os.Exit(ExitAssertionFailure)
+	}
+	dataVolume, err := parseDataVolume(*dataVolumeFlag)
 	if err != nil {
-		fmt.Println("Error loading client certificate:", err)
-		return
+		fmt.Println("Error parsing data volume:", err)
+		os.Exit(ExitAssertionFailure)
+	}
+	tlsVersion, err := parseTlsVersion(*tlsVersionFlag)
+	if err != nil {
+		fmt.Println("Error parsing tls version:", err)
+		os.Exit(ExitAssertionFailure)
+	}
+
+	// crypto/tls clients never initiate a renegotiation
+	if testCase == "renegotiation_refusal" {
+		os.Exit(ExitUnimplemented)
+	}
+	// crypto/tls doesn't allow the TLS 1.3 cipher suites to be configured
+	if *cipherSuiteFlag != "" {
+		os.Exit(ExitUnimplemented)
 	}
 
 	// Load CA certificate
@@ -37,60 +270,137 @@ func main() {
 	cert, err := os.ReadFile(certificatePath)
 	if err != nil {
 		fmt.Println("Error loading CA certificate:", err)
-		return
+		os.Exit(ExitIoError)
 	}
 
 	// Create certificate pool and add CA certificate
 	caCertPool := x509.NewCertPool()
 	if !caCertPool.AppendCertsFromPEM(cert) {
 		fmt.Println("Failed to append CA certificate")
-		return
+		os.Exit(ExitAssertionFailure)
 	}
 
 	// Create TLS configuration
 	tlsConfig := &tls.Config{
-		RootCAs: caCertPool,
+		RootCAs:    caCertPool,
+		MinVersion: tlsVersion,
+		MaxVersion: tlsVersion,
+	}
+	// mirrors InteropTest::required_group in the rust `common` crate
+	if testCase == "pq_handshake" {
+		*groupFlag = "X25519MLKEM768"
+	}
+	if *groupFlag != "" {
+		group, ok := groups[*groupFlag]
+		if !ok {
+			fmt.Println("Unrecognized group:", *groupFlag)
+			os.Exit(ExitAssertionFailure)
+		}
+		tlsConfig.CurvePreferences = []tls.CurveID{group}
 	}
 
-	if testCase == "mtls_request_response" {
+	if paths, ok := clientCertificates[testCase]; ok {
 		fmt.Println("configuring for mTLS")
+		clientCert, err := tls.LoadX509KeyPair(paths[0], paths[1])
+		if err != nil {
+			fmt.Println("Error loading client certificate:", err)
+			os.Exit(ExitAssertionFailure)
+		}
 		tlsConfig.Certificates = []tls.Certificate{clientCert}
 	}
+	alpn := alpnScenarios[testCase]
+	tlsConfig.NextProtos = alpn.offered
+	expectHandshakeFailure := expectedFailures[testCase]
+
+	address := Host + ":" + port
+	switch testCase {
+	case "sni_selection":
+		tlsConfig.ServerName = AlternateServerName
+	case "sni_absent":
+		// crypto/tls doesn't send SNI when connecting to an IP address, so the
+		// server certificate is verified against the default host manually
+		address = "127.0.0.1:" + port
+		tlsConfig.InsecureSkipVerify = true
+		tlsConfig.VerifyConnection = func(state tls.ConnectionState) error {
+			intermediates := x509.NewCertPool()
+			for _, cert := range state.PeerCertificates[1:] {
+				intermediates.AddCert(cert)
+			}
+			_, err := state.PeerCertificates[0].Verify(x509.VerifyOptions{
+				DNSName:       Host,
+				Roots:         caCertPool,
+				Intermediates: intermediates,
+			})
+			return err
+		}
+	}
 
-	// Dial the server
-	conn, err := tls.Dial("tcp", Host+":"+port, tlsConfig)
+	// Dial the server, which also performs the handshake
+	start := time.Now()
+	conn, err := tls.Dial("tcp", address, tlsConfig)
 	if err != nil {
-		fmt.Println("Error connecting:", err)
-		return
+		if expectHandshakeFailure {
+			printReceivedAlert(err)
+			fmt.Println("connection failed as expected:", err)
+			return
+		}
+		exitWithHandshakeError("Error connecting:", err)
+	}
+	if expectHandshakeFailure {
+		// TLS 1.3 clients finish the handshake before the server authenticates
+		// them, so the failure might only be observed when reading the server's
+		// response
+		_, err = conn.Write([]byte(ClientGreeting))
+		if err == nil {
+			_, err = io.ReadFull(conn, make([]byte, len(ServerGreeting)))
+		}
+		if err != nil {
+			printReceivedAlert(err)
+			fmt.Println("connection failed as expected:", err)
+			return
+		}
+		fmt.Println("connection unexpectedly succeeded")
+		os.Exit(ExitAssertionFailure)
 	}
 
+	handshake := time.Since(start)
+
 	// Create reader and writer for the connection
-	reader := bufio.NewReader(conn)
+	firstByte := &firstByteReader{reader: conn}
+	reader := bufio.NewReader(firstByte)
 	writer := bufio.NewWriter(conn)
 
 	// Perform handshake
 	err = conn.Handshake()
 	if err != nil {
-		fmt.Println("Error during handshake:", err)
-		return
+		exitWithHandshakeError("Error during handshake:", err)
 	}
 	fmt.Println("Handshake completed during testcase:", testCase)
+	printNegotiatedParams(conn.ConnectionState())
+	if conn.ConnectionState().NegotiatedProtocol != alpn.expected {
+		fmt.Println("Unexpected alpn protocol:", conn.ConnectionState().NegotiatedProtocol)
+		os.Exit(ExitAssertionFailure)
+	}
 
+	transferStart := time.Now()
+	dataBytes := 0
 	switch testCase {
 	case "handshake":
 		// No action required for handshake case
-	case "greeting", "mtls_request_response":
+	case "greeting", "mtls_request_response", "hello_retry_request", "small_tcp_packet",
+		"alpn_match", "alpn_server_preference", "alpn_server_unused", "sni_selection", "sni_absent",
+		"pq_handshake":
+		// crypto/tls doesn't expose whether a HelloRetryRequest was received,
+		// so the hello_retry_request assertion is made by the server
 		// Send client greeting
 		fmt.Println("sending the client greeting")
 		_, err = writer.WriteString(ClientGreeting)
 		if err != nil {
-			fmt.Println("Error writing data:", err)
-			return
+			exitWithError("Error writing data:", err)
 		}
 		err = writer.Flush()
 		if err != nil {
-			fmt.Println("Error flushing data:", err)
-			return
+			exitWithError("Error flushing data:", err)
 		}
 
 		// Read and verify server greeting
@@ -99,47 +409,74 @@ func main() {
 		_, err = io.ReadFull(reader, serverGreeting)
 		//serverGreeting, err := reader.ReadString('\n')
 		if err != nil {
-			fmt.Println("Error reading data:", err)
-			return
+			exitWithError("Error reading data:", err)
 		}
 		if string(serverGreeting) != ServerGreeting {
 			fmt.Println("Unexpected server greeting")
-			return
+			os.Exit(ExitAssertionFailure)
 		}
 	case "large_data_download", "large_data_download_with_frequent_key_updates":
 		// Send client greeting
 		_, err = writer.WriteString(ClientGreeting)
 		if err != nil {
-			fmt.Println("Error writing data:", err)
-			return
+			exitWithError("Error writing data:", err)
 		}
 		err = writer.Flush()
 		if err != nil {
-			fmt.Println("Error flushing data:", err)
-			return
+			exitWithError("Error flushing data:", err)
 		}
 
 		// Read and verify large data download
-		buffer := make([]byte, 1_000_000)
-		for i := 0; i < LargeDataDownloadGB; i++ {
-			for j := 0; j < 1_000; j++ {
+		dataBytes = dataVolume.TotalBytes()
+		buffer := make([]byte, BytesPerMB)
+		for i := 0; i < dataVolume.Blocks; i++ {
+			for j := 0; j < dataVolume.MbPerBlock; j++ {
 				_, err := io.ReadFull(reader, buffer)
 				if err != nil {
-					fmt.Println("Error reading data:", err)
-					return
+					exitWithError("Error reading data:", err)
 				}
 				// Check tag value
 				if int(buffer[0]) != (i % 255) {
 					fmt.Println("Unexpected tag value")
-					return
+					os.Exit(ExitAssertionFailure)
 				}
 			}
 		}
+	case "large_data_upload":
+		// crypto/tls doesn't offer an api to send a KeyUpdate, so the
+		// large_data_upload_with_frequent_key_updates scenario is unsupported
+		dataBytes = dataVolume.TotalBytes()
+		buffer := make([]byte, BytesPerMB)
+		for i := 0; i < dataVolume.Blocks; i++ {
+			// Set tag value
+			buffer[0] = byte(i % 255)
+			for j := 0; j < dataVolume.MbPerBlock; j++ {
+				_, err := writer.Write(buffer)
+				if err != nil {
+					exitWithError("Error writing data:", err)
+				}
+			}
+		}
+		err = writer.Flush()
+		if err != nil {
+			exitWithError("Error flushing data:", err)
+		}
+
+		// the server acknowledges the upload with its greeting
+		serverGreeting := make([]byte, len(ServerGreeting))
+		_, err = io.ReadFull(reader, serverGreeting)
+		if err != nil {
+			exitWithError("Error reading data:", err)
+		}
+		if string(serverGreeting) != ServerGreeting {
+			fmt.Println("Unexpected server acknowledgement")
+			os.Exit(ExitAssertionFailure)
+		}
 	default:
 		fmt.Println("Unsupported test case")
-		os.Exit(127)
-		return
+		os.Exit(ExitUnimplemented)
 	}
+	transfer := time.Since(transferStart)
 
 	fmt.Println("closing the client side of the connection");
 	conn.CloseWrite()
@@ -147,10 +484,9 @@ func main() {
 	fmt.Println("waiting for the server side to close");
 	_, err = reader.ReadByte()
 	if err != io.EOF {
-		fmt.Println("unexpected error:", err)
-		os.Exit(1)
+		exitWithError("unexpected error:", err)
 	}
-
+	printScenarioMetrics(start, handshake, firstByte.firstByte, transfer, dataBytes)
 
 	fmt.Println("Test case completed successfully.")
 }
@@ -2,11 +2,19 @@ import java.security.cert.CertificateFactory;
 import java.security.cert.X509Certificate;
 import java.security.KeyStore;
 import java.io.FileInputStream;
+import java.io.IOException;
 import java.io.InputStream;
 import java.io.OutputStream;
 import java.io.BufferedInputStream;
 import java.io.BufferedOutputStream;
+import java.io.FilterInputStream;
+import java.net.SocketTimeoutException;
+import java.util.List;
+import javax.net.ssl.SNIHostName;
+import javax.net.ssl.SNIServerName;
+import javax.net.ssl.SSLException;
 import javax.net.ssl.SSLContext;
+import javax.net.ssl.SSLParameters;
 import javax.net.ssl.TrustManagerFactory;
 import javax.net.ssl.SSLSocketFactory;
 import javax.net.ssl.SSLSocket;
@@ -16,34 +24,228 @@ import javax.net.ssl.SSLSocket;
 */
 
 public class SSLSocketClient {
-    static final int LARGE_DATA_DOWNLOAD_GB = 256;
     static final String TLS_13 = "TLSv1.3";
+    static final String TLS_12 = "TLSv1.2";
     static final String CLIENT_GREETING = "i am the client. nice to meet you server.";
     static final String SERVER_GREETING = "i am the server. a pleasure to make your acquaintance.";
     static final String HOST = "localhost";
+    static final String ALTERNATE_SERVER_NAME = "alternate.localhost";
 
-    public static void main(String[] args) throws Exception {
+    // exit codes mirror the ExitCode definition in the rust `common` crate.
+    // Invalid arguments and configuration errors are reported as an assertion
+    // failure.
+    static final int EXIT_ASSERTION_FAILURE = 1;
+    static final int EXIT_HANDSHAKE_FAILURE = 2;
+    static final int EXIT_IO_ERROR = 3;
+    static final int EXIT_TIMEOUT = 4;
+    static final int EXIT_UNIMPLEMENTED = 127;
+
+    /*
+    * Report the negotiated parameters in the format that is parsed by the runner.
+    * See NegotiatedParams in the rust `common` crate.
+    */
+    static void printNegotiatedParams(SSLSocket socket) {
+        String alpn = socket.getApplicationProtocol();
+        if (alpn == null || alpn.isEmpty()) {
+            alpn = "-";
+        }
+        System.out.println("negotiated_params version=" + socket.getSession().getProtocol()
+            + " cipher_suite=" + socket.getSession().getCipherSuite()
+            + " alpn=" + alpn);
+    }
+
+    /*
+    * Report the metrics of the connection in the format that is parsed by the
+    * runner. See ScenarioMetrics in the rust `common` crate. The JDK doesn't
+    * expose key update counters, so they are omitted. Times are System.nanoTime
+    * values, and firstByte and dataBytes are null if they weren't measured.
+    */
+    static void printScenarioMetrics(long start, long handshake, Long firstByte, long transfer, Long dataBytes) {
+        StringBuilder metrics = new StringBuilder("scenario_metrics handshake_ms=" + formatMillis(handshake));
+        if (firstByte != null) {
+            metrics.append(" ttfb_ms=" + formatMillis(firstByte - start));
+        }
+        metrics.append(" transfer_ms=" + formatMillis(transfer));
+        if (dataBytes != null) {
+            metrics.append(" data_bytes=" + dataBytes);
+            if (transfer > 0) {
+                metrics.append(String.format(" throughput_bytes_per_s=%.0f", dataBytes / (transfer / 1e9)));
+            }
+        }
+        System.out.println(metrics);
+    }
+
+    /*
+    * Format a duration in milliseconds with microsecond precision
+    */
+    static String formatMillis(long nanos) {
+        long micros = nanos / 1_000;
+        return String.format("%d.%03d", micros / 1_000, micros % 1_000);
+    }
+
+    /*
+    * Report a received alert in the format that is parsed by the runner. See
+    * TlsAlert in the rust `common` crate. The JDK doesn't expose alerts, but
+    * the message of the exception names a received alert, e.g.
+    * "Received fatal alert: unknown_ca", or a warning that failed a handshake,
+    * e.g. "received handshake warning: no_renegotiation".
+    */
+    static void printReceivedAlert(Throwable e) {
+        final String[] prefixes = {"Received fatal alert: ", "received handshake warning: "};
+        for (Throwable cause = e; cause != null; cause = cause.getCause()) {
+            String message = cause.getMessage();
+            for (String prefix : prefixes) {
+                if (message != null && message.startsWith(prefix)) {
+                    System.out.println("tls_alert received=" + message.substring(prefix.length()));
+                    return;
+                }
+            }
+        }
+    }
+
+    public static void main(String[] args) {
+        try {
+            run(args);
+        } catch (Exception e) {
+            e.printStackTrace();
+            System.exit(exitCode(e));
+        }
+    }
+
+    /*
+    * Classify the exception that failed a scenario. Like the rust shims, a
+    * connection that was closed with an alert is a handshake failure.
+    */
+    static int exitCode(Exception e) {
+        if (e instanceof SocketTimeoutException) {
+            return EXIT_TIMEOUT;
+        } else if (e instanceof SSLException) {
+            return EXIT_HANDSHAKE_FAILURE;
+        } else if (e instanceof IOException) {
+            return EXIT_IO_ERROR;
+        }
+        return EXIT_ASSERTION_FAILURE;
+    }
+
+    static void run(String[] args) throws Exception {
         // enable debug logging for better visibility into SSL and TLS internals
         System.setProperty("javax.net.debug", "ssl");
 
         // parse the test arguments
         String testCase = args[0];
         int port = Integer.parseInt(args[1]);
+        DataVolume dataVolume = DataVolume.FULL;
+        String protocol = TLS_13;
+        // the pinned cipher suite and group use the IANA names, which the JDK
+        // also uses. See CipherSuite and KeyExchangeGroup in the rust `common` crate.
+        String cipherSuite = null;
+        String group = null;
+        for (int i = 2; i < args.length; i += 2) {
+            if (i + 1 == args.length) {
+                throw new IllegalArgumentException("missing value for argument: " + args[i]);
+            }
+            if (args[i].equals("--data-volume")) {
+                dataVolume = DataVolume.parse(args[i + 1]);
+            } else if (args[i].equals("--tls-version")) {
+                protocol = parseTlsVersion(args[i + 1]);
+            } else if (args[i].equals("--cipher-suite")) {
+                cipherSuite = args[i + 1];
+            } else if (args[i].equals("--group")) {
+                group = args[i + 1];
+            } else {
+                throw new IllegalArgumentException("unrecognized argument: " + args[i]);
+            }
+        }
+
+        // client certificates aren't supported
+        if (testCase.equals("mtls_untrusted_client_cert")) {
+            System.exit(EXIT_UNIMPLEMENTED);
+        }
+        // the JDK doesn't support hybrid key exchange groups
+        if (testCase.equals("pq_handshake") || "X25519MLKEM768".equals(group)) {
+            System.exit(EXIT_UNIMPLEMENTED);
+        }
+
+        if (group != null) {
+            // SSLParameters only exposes the named groups since JDK 20, so the
+            // groups are restricted for the whole process instead
+            System.setProperty("jdk.tls.namedGroups", group);
+        }
 
         String certificatePath = "../certificates/ca-cert.pem";
-        SSLSocketFactory socketFactory = createSocketFactory(certificatePath, TLS_13);
+        SSLSocketFactory socketFactory = createSocketFactory(certificatePath, protocol);
         try (
             SSLSocket socket = (SSLSocket)socketFactory.createSocket(HOST, port);
         ) {
-            InputStream in = new BufferedInputStream(socket.getInputStream());
+            FirstByteInputStream firstByte = new FirstByteInputStream(socket.getInputStream());
+            InputStream in = new BufferedInputStream(firstByte);
             OutputStream out = new BufferedOutputStream(socket.getOutputStream());
 
+            SSLParameters parameters = socket.getSSLParameters();
+            if (verifiesHostName(testCase)) {
+                // authenticate the server's host name as well as its certificate chain
+                parameters.setEndpointIdentificationAlgorithm("HTTPS");
+            }
+            // only enable the requested protocol version
+            parameters.setProtocols(new String[] {protocol});
+            if (cipherSuite != null) {
+                parameters.setCipherSuites(new String[] {cipherSuite});
+            }
+            parameters.setApplicationProtocols(clientAlpnProtocols(testCase));
+            if (testCase.equals("sni_selection") || testCase.equals("sni_absent")) {
+                parameters.setServerNames(serverNames(testCase));
+            }
+            socket.setSSLParameters(parameters);
+
+            if (expectsHandshakeFailure(testCase)) {
+                try {
+                    socket.startHandshake();
+                    if (testCase.equals("renegotiation_refusal")) {
+                        // calling startHandshake on an established TLS 1.2
+                        // connection starts a renegotiation, which the server
+                        // is expected to refuse
+                        socket.startHandshake();
+                    }
+                    // TLS 1.3 clients finish the handshake before the server
+                    // authenticates them, so the failure might only be observed
+                    // when reading the server's response
+                    out.write(CLIENT_GREETING.getBytes());
+                    out.flush();
+                    byte[] buffer = in.readNBytes(SERVER_GREETING.getBytes().length);
+                    if (buffer.length < SERVER_GREETING.getBytes().length) {
+                        System.out.println("connection failed as expected: the server closed the connection");
+                        return;
+                    }
+                } catch (IOException e) {
+                    printReceivedAlert(e);
+                    System.out.println("connection failed as expected: " + e);
+                    return;
+                }
+                throw new Exception("connection unexpectedly succeeded");
+            }
+
+            long start = System.nanoTime();
             socket.startHandshake();
+            long handshake = System.nanoTime() - start;
             System.out.println("handshake completed during testcase: " + testCase);
+            printNegotiatedParams(socket);
+            if (testCase.equals("sni_selection") || testCase.equals("sni_absent")) {
+                checkServerIdentity(socket, testCase);
+            }
+            // an empty string indicates that no protocol was selected
+            if (!socket.getApplicationProtocol().equals(expectedAlpnProtocol(testCase))) {
+                throw new Exception("Unexpected alpn protocol: " + socket.getApplicationProtocol());
+            }
 
+            long transferStart = System.nanoTime();
+            Long dataBytes = null;
             if (testCase.equals("handshake")) {
                 // no action required for handshake case
-            } else if (testCase.equals("greeting")) {
+            } else if (testCase.equals("greeting") || testCase.equals("hello_retry_request") || testCase.equals("small_tcp_packet")
+                    || testCase.equals("alpn_match") || testCase.equals("alpn_server_preference") || testCase.equals("alpn_server_unused")
+                    || testCase.equals("sni_selection") || testCase.equals("sni_absent")) {
+                // the JDK doesn't expose whether a HelloRetryRequest was received,
+                // so the hello_retry_request assertion is made by the server
                 out.write(CLIENT_GREETING.getBytes());
                 out.flush();
 
@@ -53,30 +255,67 @@ public class SSLSocketClient {
                 if (!s.equals(SERVER_GREETING)) {
                     throw new Exception("Unexpected server greeting");
                 }
+            } else if (testCase.equals("key_update_request")) {
+                // calling startHandshake on an established TLS 1.3 connection
+                // sends a KeyUpdate with update_requested set. The JDK doesn't
+                // expose key update counters, so the assertion is made by the server
+                socket.startHandshake();
+                out.write(CLIENT_GREETING.getBytes());
+                out.flush();
+
+                byte[] buffer = in.readNBytes(SERVER_GREETING.getBytes().length);
+                if (!new String(buffer).equals(SERVER_GREETING)) {
+                    throw new Exception("Unexpected server greeting");
+                }
             } else if (testCase.equals("large_data_download") || testCase.equals("large_data_download_with_frequent_key_updates")) {
                 out.write(CLIENT_GREETING.getBytes());
                 out.flush();
-                byte[] buffer = new byte[1_000_000];
-                for (int i = 0; i < LARGE_DATA_DOWNLOAD_GB; i++) {
-                    for (int j = 0; j < 1_000; j++) {
-                        int len = in.readNBytes(buffer, 0, 1_000_000);
-                        if (len != 1_000_000) {
+                dataBytes = dataVolume.totalBytes();
+                byte[] buffer = new byte[DataVolume.BYTES_PER_MB];
+                for (int i = 0; i < dataVolume.blocks; i++) {
+                    for (int j = 0; j < dataVolume.mbPerBlock; j++) {
+                        int len = in.readNBytes(buffer, 0, DataVolume.BYTES_PER_MB);
+                        if (len != DataVolume.BYTES_PER_MB) {
                             throw new Exception("Unexpected end of stream");
                         }
                         // java bytes are signed, so we have to upcast to an int to 
                         // read the tag value
                         int tag = buffer[0] & 0xFF;
                         if (tag != (i % 255)) {
-                            System.out.println("unexpected tag value. Mb:" +(i * 1_000 + j) +" Expected:" + i + " received:" + tag);
+                            System.out.println("unexpected tag value. Mb:" +(i * dataVolume.mbPerBlock + j) +" Expected:" + i + " received:" + tag);
                             System.out.println("unexpected tag value. Expected:" + i + " received:" + tag);
                             throw new Exception("Unexpected tag value");
                         }
                     }
                 }
+            } else if (testCase.equals("large_data_upload") || testCase.equals("large_data_upload_with_frequent_key_updates")) {
+                boolean frequentKeyUpdates = testCase.equals("large_data_upload_with_frequent_key_updates");
+                dataBytes = dataVolume.totalBytes();
+                byte[] buffer = new byte[DataVolume.BYTES_PER_MB];
+                for (int i = 0; i < dataVolume.blocks; i++) {
+                    if (frequentKeyUpdates) {
+                        // calling startHandshake on an established TLS 1.3
+                        // connection sends a KeyUpdate message
+                        out.flush();
+                        socket.startHandshake();
+                    }
+                    buffer[0] = (byte) (i % 255);
+                    for (int j = 0; j < dataVolume.mbPerBlock; j++) {
+                        out.write(buffer);
+                    }
+                }
+                out.flush();
+
+                // the server acknowledges the upload with its greeting
+                byte[] ack = in.readNBytes(SERVER_GREETING.getBytes().length);
+                if (!new String(ack).equals(SERVER_GREETING)) {
+                    throw new Exception("Unexpected server acknowledgement");
+                }
             } else {
                 // unsupported test case
-                System.exit(127);
+                System.exit(EXIT_UNIMPLEMENTED);
             }
+            long transfer = System.nanoTime() - transferStart;
             // close the client side of the connection
             System.out.println("closing the client side of the connection");
             out.flush();
@@ -95,6 +334,189 @@ public class SSLSocketClient {
             if (closed != -1) {
                 throw new Exception("server side unexpectedly open");
             }
+            printScenarioMetrics(start, handshake, firstByte.firstByte, transfer, dataBytes);
+        }
+    }
+
+    /*
+    * The scenarios that assert on the server's host name. Other scenarios only
+    * authenticate the certificate chain, like the JDK's default.
+    */
+    static boolean verifiesHostName(String testCase) {
+        switch (testCase) {
+            case "hostname_mismatch":
+            case "sni_selection":
+            case "sni_absent":
+                return true;
+            default:
+                return false;
+        }
+    }
+
+    /*
+    * Mirrors InteropTest::expects_handshake_failure in the rust `common` crate
+    */
+    static boolean expectsHandshakeFailure(String testCase) {
+        switch (testCase) {
+            case "alpn_no_overlap":
+            case "untrusted_server_ca":
+            case "hostname_mismatch":
+            case "expired_server_cert":
+            case "mtls_missing_client_cert":
+            case "mtls_untrusted_client_cert":
+            case "renegotiation_refusal":
+                return true;
+            default:
+                return false;
+        }
+    }
+
+    /*
+    * Mirrors TlsVersion in the rust `common` crate
+    */
+    static String parseTlsVersion(String s) {
+        switch (s) {
+            case "tls1.2":
+                return TLS_12;
+            case "tls1.3":
+                return TLS_13;
+            default:
+                throw new IllegalArgumentException("unrecognized tls version: " + s);
+        }
+    }
+
+    /*
+    * Mirrors the ALPN configuration of the InteropTest in the rust `common` crate
+    */
+    static String[] clientAlpnProtocols(String testCase) {
+        switch (testCase) {
+            case "alpn_match":
+            case "alpn_server_unused":
+                return new String[] {"h2", "http/1.1"};
+            case "alpn_server_preference":
+                return new String[] {"http/1.1", "h2"};
+            case "alpn_no_overlap":
+                return new String[] {"h2"};
+            default:
+                return new String[] {};
+        }
+    }
+
+    static String expectedAlpnProtocol(String testCase) {
+        switch (testCase) {
+            case "alpn_match":
+            case "alpn_server_preference":
+                return "h2";
+            default:
+                return "";
+        }
+    }
+
+    /*
+    * Mirrors the server name of the InteropTest in the rust `common` crate. The
+    * JDK doesn't send SNI for "localhost" because it isn't a fully qualified
+    * domain name, so the default server name is never sent.
+    */
+    static List<SNIServerName> serverNames(String testCase) {
+        if (testCase.equals("sni_selection")) {
+            return List.of(new SNIHostName(ALTERNATE_SERVER_NAME));
+        }
+        return List.of();
+    }
+
+    /*
+    * The JDK's endpoint identification falls back to the host that it connected
+    * to if the certificate isn't valid for the SNI host name, so the selected
+    * certificate is checked explicitly.
+    */
+    static void checkServerIdentity(SSLSocket socket, String testCase) throws Exception {
+        String expected = testCase.equals("sni_selection") ? ALTERNATE_SERVER_NAME : HOST;
+        X509Certificate leaf = (X509Certificate) socket.getSession().getPeerCertificates()[0];
+        if (leaf.getSubjectAlternativeNames() != null) {
+            for (List<?> name : leaf.getSubjectAlternativeNames()) {
+                if (expected.equals(name.get(1))) {
+                    return;
+                }
+            }
+        }
+        throw new Exception("Unexpected server certificate: " + leaf.getSubjectX500Principal());
+    }
+
+    /*
+    * Mirrors the DataVolume definition in the rust `common` crate. Large data is
+    * sent in blocks of 1 MB writes, and the first byte of each MB is the index
+    * of its block (mod 255).
+    */
+    static class DataVolume {
+        static final DataVolume QUICK = new DataVolume(8, 1);
+        static final DataVolume FULL = new DataVolume(256, 1_000);
+        static final int BYTES_PER_MB = 1_000_000;
+
+        final int blocks;
+        final int mbPerBlock;
+
+        DataVolume(int blocks, int mbPerBlock) {
+            this.blocks = blocks;
+            this.mbPerBlock = mbPerBlock;
+        }
+
+        long totalBytes() {
+            return (long) blocks * mbPerBlock * BYTES_PER_MB;
+        }
+
+        static DataVolume parse(String s) {
+            if (s.equals("quick")) {
+                return QUICK;
+            } else if (s.equals("full")) {
+                return FULL;
+            }
+            // like the rust parser, accept exactly "<blocks>x<mb per block>" with
+            // non-negative counts
+            String[] parts = s.split("x", -1);
+            if (parts.length != 2) {
+                throw new IllegalArgumentException("unrecognized data volume: " + s);
+            }
+            int blocks = Integer.parseInt(parts[0]);
+            int mbPerBlock = Integer.parseInt(parts[1]);
+            if (blocks < 0 || mbPerBlock < 0) {
+                throw new IllegalArgumentException("unrecognized data volume: " + s);
+            }
+            return new DataVolume(blocks, mbPerBlock);
+        }
+    }
+
+    /*
+    * Records when the first byte of application data was read
+    */
+    static class FirstByteInputStream extends FilterInputStream {
+        Long firstByte = null;
+
+        FirstByteInputStream(InputStream in) {
+            super(in);
+        }
+
+        @Override
+        public int read() throws IOException {
+            int b = super.read();
+            if (b != -1) {
+                record();
+            }
+            return b;
+        }
+
+        @Override
+        public int read(byte[] b, int off, int len) throws IOException {
+            int n = super.read(b, off, len);
+            if (n > 0) {
+                record();
+            }
+            return n;
+        }
+
+        private void record() {
+            if (firstByte == null) {
+                firstByte = System.nanoTime();
+            }
         }
     }
 
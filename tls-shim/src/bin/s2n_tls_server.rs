@@ -1,64 +1,12 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{
-    error::Error,
-    net::{Ipv4Addr, SocketAddrV4},
-    process::exit,
-};
-use tls_shim_interop::{s2n_tls_shim::S2NShim, ServerTLS};
-use tokio::net::{TcpListener, TcpStream};
-use tracing::Level;
-
-use common::InteropTest;
-
-// while it would be convenient to make this function generic over Tls: ServerTls<Stream>
-// the rust compiler type inference isn't advanced enough to add send bounds to
-// the futures that get calculate in that case.
-async fn run_server(
-    config: <S2NShim as ServerTLS<TcpStream>>::Config,
-    port: u16,
-    test: InteropTest,
-) -> Result<(), Box<dyn Error + Send + Sync>> {
-    let server = <S2NShim as ServerTLS<TcpStream>>::acceptor(config);
-
-    let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)).await?;
-    let (stream, peer_addr) = listener.accept().await?;
-    tracing::info!("Connection from {:?}", peer_addr);
-
-    let tls = <S2NShim as ServerTLS<TcpStream>>::accept(&server, stream).await?;
-    <S2NShim as ServerTLS<TcpStream>>::handle_server_connection(test, tls).await?;
+//! An alias for `tls-shim server --impl s2n-tls`.
 
-    Ok(())
-}
+use tls_shim_interop::cli::{self, ServerImpl};
+use tracing::Level;
 
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    tracing_subscriber::fmt::fmt()
-        .with_max_level(Level::INFO)
-        .with_ansi(false)
-        .init();
-
-    let (test, port) = common::parse_server_arguments();
-    let config = match <S2NShim as ServerTLS<TcpStream>>::get_server_config(test)? {
-        Some(c) => c,
-        // if the test case isn't supported, return 127
-        None => exit(127),
-    };
-    if test == InteropTest::SessionResumption {
-        // if we are testing session resumption, we first do a basic "request reseponse" behavior.
-        // this ends up sending the session ticket, then when we execute the real `SessionResumption`
-        // scenario we validate that session resumption actually happened
-        if let Err(e) = run_server(config.clone(), port, InteropTest::Greeting).await {
-            tracing::error!("test scenario failed: {:?}", e);
-            exit(1);
-        }
-    }
-    if let Err(e) = run_server(config.clone(), port, test).await {
-        tracing::error!("test scenario failed: {:?}", e);
-        exit(1);
-    }
-
-
-    Ok(())
+async fn main() {
+    cli::server_alias(ServerImpl::S2nTls, Level::INFO).await
 }
@@ -0,0 +1,54 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use clap::{Parser, Subcommand};
+use tls_shim_interop::cli::{self, ClientImpl, Scenario, ServerImpl};
+use tracing::Level;
+
+/// Run one side of an interop scenario with any of the TLS implementations.
+#[derive(Parser, Debug)]
+#[clap(name = "tls-shim")]
+struct Cli {
+    #[clap(subcommand)]
+    role: Role,
+}
+
+#[derive(Subcommand, Debug)]
+enum Role {
+    /// Listen on the port and run the server side of the scenario
+    Server {
+        #[clap(long = "impl", arg_enum)]
+        implementation: ServerImpl,
+        #[clap(flatten)]
+        scenario: Scenario,
+    },
+    /// Connect to the port and run the client side of the scenario
+    Client {
+        #[clap(long = "impl", arg_enum)]
+        implementation: ClientImpl,
+        #[clap(flatten)]
+        scenario: Scenario,
+    },
+}
+
+#[tokio::main]
+async fn main() {
+    cli::init(Level::INFO);
+    let arguments: Cli = cli::parse();
+    match arguments.role {
+        Role::Server {
+            implementation,
+            scenario,
+        } => {
+            let parameters = scenario.parameters();
+            cli::server_main(implementation, scenario.test, scenario.port, parameters).await
+        }
+        Role::Client {
+            implementation,
+            scenario,
+        } => {
+            let parameters = scenario.parameters();
+            cli::client_main(implementation, scenario.test, scenario.port, parameters).await
+        }
+    }
+}
@@ -0,0 +1,215 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The entry points of the shim binaries. The `tls-shim` binary selects the
+//! implementation on the command line, and the per-implementation binaries,
+//! e.g. `s2n_tls_server`, are aliases for one of its subcommands.
+
+use std::{
+    error::Error,
+    io,
+    net::{Ipv4Addr, SocketAddrV4},
+};
+
+use common::{
+    CipherSuite, DataVolume, ExitCode, InteropTest, KeyExchangeGroup, TestParameters, TlsVersion,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::Level;
+
+use crate::{
+    driver, openssl_shim::OpensslShim, rustls_shim::RustlsShim, s2n_tls_shim::S2NShim,
+    transport::FragmentingStream, ClientTLS, ConnectionFailure, ServerTLS,
+};
+
+type Transport = FragmentingStream<TcpStream>;
+
+/// The implementations that can run as a server.
+#[derive(clap::ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerImpl {
+    S2nTls,
+    Openssl,
+}
+
+/// The implementations that can run as a client.
+#[derive(clap::ArgEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientImpl {
+    S2nTls,
+    Rustls,
+}
+
+/// The same arguments that the runner passes to the per-implementation
+/// binaries.
+#[derive(clap::Parser, Debug)]
+pub struct Scenario {
+    /// The scenario to run, e.g. `greeting`
+    pub test: InteropTest,
+    pub port: u16,
+    /// A named profile, e.g. `quick`, or `{blocks}x{mb_per_block}`
+    #[clap(long)]
+    pub data_volume: Option<DataVolume>,
+    /// `tls1.3` or `tls1.2`
+    #[clap(long)]
+    pub tls_version: Option<TlsVersion>,
+    /// The only TLS 1.3 cipher suite to enable, e.g. `TLS_AES_128_GCM_SHA256`
+    #[clap(long)]
+    pub cipher_suite: Option<CipherSuite>,
+    /// The only key exchange group to enable, e.g. `x25519`
+    #[clap(long)]
+    pub group: Option<KeyExchangeGroup>,
+}
+
+impl Scenario {
+    pub fn parameters(&self) -> TestParameters {
+        let defaults = TestParameters::default();
+        TestParameters {
+            data_volume: self.data_volume.unwrap_or(defaults.data_volume),
+            tls_version: self.tls_version.unwrap_or(defaults.tls_version),
+            cipher_suite: self.cipher_suite,
+            group: self.group,
+        }
+    }
+}
+
+/// Parse the command line. clap exits with 2 for invalid arguments, which is
+/// a handshake failure in the exit code contract, so they are reported as an
+/// assertion failure instead.
+pub fn parse<P: clap::Parser>() -> P {
+    P::try_parse().unwrap_or_else(|e| {
+        if !e.use_stderr() {
+            // the help or version was requested
+            e.exit();
+        }
+        let _ = e.print();
+        ExitCode::AssertionFailure.exit()
+    })
+}
+
+/// Set up logging at `level`, and report panics with the [`ExitCode`]
+/// contract. This must be called before the arguments are parsed, so that
+/// invalid arguments are reported as well.
+pub fn init(level: Level) {
+    tracing_subscriber::fmt::fmt()
+        .with_max_level(level)
+        .with_ansi(false)
+        .init();
+
+    // the shims unwrap their configuration, so a panic is an assertion failure
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        default_hook(info);
+        ExitCode::AssertionFailure.exit();
+    }));
+}
+
+/// The entry point of the per-implementation server binaries, which take the
+/// same arguments as `tls-shim server`.
+pub async fn server_alias(implementation: ServerImpl, level: Level) -> ! {
+    init(level);
+    let scenario: Scenario = parse();
+    let parameters = scenario.parameters();
+    server_main(implementation, scenario.test, scenario.port, parameters).await
+}
+
+/// The entry point of the per-implementation client binaries, which take the
+/// same arguments as `tls-shim client`.
+pub async fn client_alias(implementation: ClientImpl, level: Level) -> ! {
+    init(level);
+    let scenario: Scenario = parse();
+    let parameters = scenario.parameters();
+    client_main(implementation, scenario.test, scenario.port, parameters).await
+}
+
+/// Run the server side of `test` with `implementation`, listening on `port`,
+/// and exit with the [`ExitCode`] that reports the outcome.
+pub async fn server_main(
+    implementation: ServerImpl,
+    test: InteropTest,
+    port: u16,
+    parameters: TestParameters,
+) -> ! {
+    let outcome = match implementation {
+        ServerImpl::S2nTls => run_server::<S2NShim>(test, port, parameters).await,
+        ServerImpl::Openssl => run_server::<OpensslShim>(test, port, parameters).await,
+    };
+    exit(outcome)
+}
+
+/// Run the client side of `test` with `implementation`, connecting to `port`,
+/// and exit with the [`ExitCode`] that reports the outcome.
+pub async fn client_main(
+    implementation: ClientImpl,
+    test: InteropTest,
+    port: u16,
+    parameters: TestParameters,
+) -> ! {
+    let outcome = match implementation {
+        ClientImpl::S2nTls => run_client::<S2NShim>(test, port, parameters).await,
+        ClientImpl::Rustls => run_client::<RustlsShim>(test, port, parameters).await,
+    };
+    exit(outcome)
+}
+
+fn exit(outcome: Result<(), Box<dyn Error>>) -> ! {
+    let Err(e) = outcome else {
+        ExitCode::Success.exit();
+    };
+    tracing::error!("test scenario failed: {}", e);
+    let exit_code = match e.downcast_ref::<ConnectionFailure>() {
+        Some(failure) => failure.exit_code,
+        // the connection couldn't be established
+        None if e.is::<io::Error>() => ExitCode::IoError,
+        // the configuration couldn't be loaded
+        None => ExitCode::AssertionFailure,
+    };
+    exit_code.exit()
+}
+
+async fn run_server<Tls: ServerTLS<Transport>>(
+    test: InteropTest,
+    port: u16,
+    parameters: TestParameters,
+) -> Result<(), Box<dyn Error>> {
+    let config = match Tls::get_server_config(test, parameters)? {
+        Some(c) => c,
+        None => ExitCode::Unimplemented.exit(),
+    };
+    // the session cache and the session ticket keys belong to the acceptor, so
+    // the same acceptor must be used for both connections of the resumption
+    // scenarios
+    let server = Tls::acceptor(config);
+    // the listener is bound once, so that the client can't reconnect before
+    // the server starts listening again in the resumption scenarios
+    let listener = TcpListener::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)).await?;
+    for test in driver::server_scenarios(test) {
+        let (stream, peer_addr) = listener.accept().await?;
+        tracing::info!("Connection from {:?}", peer_addr);
+        let stream = FragmentingStream::for_test(stream, test);
+        driver::accept::<Tls, _>(&server, stream, test, parameters)
+            .await
+            .map_err(|e| e as Box<dyn Error>)?;
+    }
+    Ok(())
+}
+
+async fn run_client<Tls: ClientTLS<Transport>>(
+    test: InteropTest,
+    port: u16,
+    parameters: TestParameters,
+) -> Result<(), Box<dyn Error>> {
+    let config = match Tls::get_client_config(test, parameters)? {
+        Some(c) => c,
+        None => ExitCode::Unimplemented.exit(),
+    };
+    // the session cache belongs to the connector, so the second connection of
+    // the resumption scenarios resumes the session of the first connection
+    let client = Tls::connector(config);
+    for test in driver::client_scenarios(test) {
+        let stream = TcpStream::connect(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)).await?;
+        let stream = FragmentingStream::for_test(stream, test);
+        driver::connect::<Tls, _>(&client, stream, test, parameters)
+            .await
+            .map_err(|e| e as Box<dyn Error>)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,216 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The driver runs scenarios between a [`ServerTLS`] and a [`ClientTLS`]
+//! implementation. The shim binaries, the turmoil tests and the in-memory
+//! tests all run connections through [`accept`] and [`connect`], and only
+//! differ in the transport that they provide.
+//!
+//! None of the functions in this module spawn tasks, so the futures don't
+//! need to be `Send`. This is what allows them to be generic over the
+//! implementation, since the futures returned by the `async fn`s in the shim
+//! traits aren't known to be `Send`.
+
+use std::{error::Error, time::Duration};
+
+use common::{InteropTest, TestParameters};
+use tokio::{io::DuplexStream, time::Instant};
+
+use crate::{
+    check_outcome, transport::FragmentingStream, ClientTLS, ConnectionFailure, ConnectionMetrics,
+    InteropError, ServerTLS,
+};
+
+/// The buffer size of each direction of an in-memory connection, which plays
+/// the part of the TCP receive window.
+const DUPLEX_CAPACITY: usize = 64 * 1024;
+
+/// The scenarios that the server runs on each connection of `test`.
+///
+/// The resumption scenarios first run a basic "request response" connection,
+/// which issues the session ticket, so that the second connection can validate
+/// that the session was actually resumed.
+pub fn server_scenarios(test: InteropTest) -> Vec<InteropTest> {
+    if test.resumes_session() {
+        vec![InteropTest::Greeting, test]
+    } else {
+        vec![test]
+    }
+}
+
+/// The scenarios that the client runs on each connection of `test`. The client
+/// runs the same scenario on both connections of the resumption scenarios.
+pub fn client_scenarios(test: InteropTest) -> Vec<InteropTest> {
+    if test.resumes_session() {
+        vec![test, test]
+    } else {
+        vec![test]
+    }
+}
+
+/// Accept a single connection over `transport_stream`, run `test` on it, and
+/// check the outcome against the expectations of `test`. A failure is returned
+/// as a [`ConnectionFailure`].
+pub async fn accept<S, T>(
+    acceptor: &S::Acceptor,
+    transport_stream: T,
+    test: InteropTest,
+    parameters: TestParameters,
+) -> Result<(), Box<dyn Error + Send + Sync>>
+where
+    S: ServerTLS<T>,
+{
+    let start = Instant::now();
+    let (handshake_complete, outcome) = match S::accept(acceptor, transport_stream).await {
+        Ok(tls) => {
+            let handshake = start.elapsed();
+            let outcome = S::handle_server_connection(test, parameters, tls).await;
+            (
+                true,
+                report_metrics(outcome, test, parameters, start, handshake),
+            )
+        }
+        Err(e) => (false, Err(e)),
+    };
+    check_outcome(test, outcome, S::alert)
+        .map_err(|e| ConnectionFailure::new(e, handshake_complete, S::alert).into())
+}
+
+/// Connect over `transport_stream`, run `test` on the connection, and check the
+/// outcome against the expectations of `test`. A failure is returned as a
+/// [`ConnectionFailure`].
+pub async fn connect<C, T>(
+    connector: &C::Connector,
+    transport_stream: T,
+    test: InteropTest,
+    parameters: TestParameters,
+) -> Result<(), Box<dyn Error + Send + Sync>>
+where
+    C: ClientTLS<T>,
+{
+    let start = Instant::now();
+    let (handshake_complete, outcome) =
+        match C::connect(connector, test.server_name(), transport_stream).await {
+            Ok(tls) => {
+                let handshake = start.elapsed();
+                let outcome = C::handle_client_connection(test, parameters, tls).await;
+                (
+                    true,
+                    report_metrics(outcome, test, parameters, start, handshake),
+                )
+            }
+            Err(e) => (false, Err(e)),
+        };
+    check_outcome(test, outcome, C::alert)
+        .map_err(|e| ConnectionFailure::new(e, handshake_complete, C::alert).into())
+}
+
+/// Log the [common::ScenarioMetrics] of a connection that ran its scenario to
+/// completion, which started the handshake at `start`.
+fn report_metrics(
+    outcome: Result<ConnectionMetrics, Box<dyn Error + Send + Sync>>,
+    test: InteropTest,
+    parameters: TestParameters,
+    start: Instant,
+    handshake: Duration,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let metrics = outcome?.scenario_metrics(test, parameters, start, handshake);
+    tracing::info!("{}", metrics);
+    Ok(())
+}
+
+/// Run `test` between an `S` server and a `C` client over in-memory
+/// connections. Scenarios that either implementation doesn't support fail
+/// with [`InteropError::UnsupportedScenario`].
+pub async fn run_in_memory<S, C>(
+    test: InteropTest,
+    parameters: TestParameters,
+) -> Result<(), Box<dyn Error>>
+where
+    S: ServerTLS<FragmentingStream<DuplexStream>>,
+    C: ClientTLS<FragmentingStream<DuplexStream>>,
+{
+    let server_config = S::get_server_config(test, parameters)?;
+    let client_config = C::get_client_config(test, parameters)?;
+    let (Some(server_config), Some(client_config)) = (server_config, client_config) else {
+        return Err(InteropError::UnsupportedScenario(test).into());
+    };
+    // the session cache and the session ticket keys belong to the acceptor and
+    // the connector, so they are shared by every connection
+    let acceptor = S::acceptor(server_config);
+    let connector = C::connector(client_config);
+
+    for (server_test, client_test) in server_scenarios(test)
+        .into_iter()
+        .zip(client_scenarios(test))
+    {
+        let (client_stream, server_stream) = tokio::io::duplex(DUPLEX_CAPACITY);
+        // a peer that fails drops its end of the connection, so the other peer
+        // sees the connection close instead of waiting forever
+        let (server_outcome, client_outcome) = tokio::join!(
+            accept::<S, _>(
+                &acceptor,
+                FragmentingStream::for_test(server_stream, server_test),
+                server_test,
+                parameters,
+            ),
+            connect::<C, _>(
+                &connector,
+                FragmentingStream::for_test(client_stream, client_test),
+                client_test,
+                parameters,
+            ),
+        );
+        server_outcome.map_err(|e| format!("the server failed {server_test}: {e}"))?;
+        client_outcome.map_err(|e| format!("the client failed {client_test}: {e}"))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{openssl_shim::OpensslShim, rustls_shim::RustlsShim, s2n_tls_shim::S2NShim};
+    use common::{DataVolume, TlsVersion};
+
+    fn parameters() -> TestParameters {
+        TestParameters {
+            data_volume: DataVolume {
+                blocks: 2,
+                mb_per_block: 1,
+            },
+            tls_version: TlsVersion::Tls13,
+            cipher_suite: None,
+            group: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn greeting() {
+        run_in_memory::<S2NShim, RustlsShim>(InteropTest::Greeting, parameters())
+            .await
+            .unwrap();
+        run_in_memory::<OpensslShim, S2NShim>(InteropTest::Greeting, parameters())
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn session_resumption() {
+        run_in_memory::<S2NShim, S2NShim>(InteropTest::SessionResumption, parameters())
+            .await
+            .unwrap();
+        run_in_memory::<OpensslShim, RustlsShim>(InteropTest::SessionResumption, parameters())
+            .await
+            .unwrap();
+    }
+
+    // s2n-tls blinds failed handshakes, which only takes simulated time when
+    // the clock is paused
+    #[tokio::test(start_paused = true)]
+    async fn expected_handshake_failure() {
+        run_in_memory::<S2NShim, RustlsShim>(InteropTest::UntrustedServerCa, parameters())
+            .await
+            .unwrap();
+    }
+}
@@ -11,18 +11,492 @@
 // "impl Future" syntax for the more readable compiler errors that it provides.
 #![allow(async_fn_in_trait)]
 
-use std::{error::Error, fmt::Debug};
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::{
+    error::Error,
+    fmt::{Debug, Display},
+    io,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    time::Instant,
+};
 
-use common::{InteropTest, CLIENT_GREETING, LARGE_DATA_DOWNLOAD_GB, SERVER_GREETING};
+use common::{
+    DataVolume, ExitCode, InteropTest, KeyExchangeGroup, NegotiatedParams, ScenarioMetrics,
+    TestParameters, TlsAlert, CLIENT_GREETING, EXPECTED_FAILURE_LOG, SERVER_GREETING,
+};
 use tracing::{error, info};
 
+pub mod cli;
+pub mod driver;
 pub mod openssl_shim;
 pub mod rustls_shim;
 pub mod s2n_tls_shim;
+pub mod transport;
 
-const ONE_MB: usize = 1_000_000;
-const ONE_GB: usize = 1_000_000_000;
+const ONE_MB: usize = DataVolume::BYTES_PER_MB as usize;
+
+/// The number of TLS 1.3 KeyUpdate messages that a connection has sent and
+/// received.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct KeyUpdateCounts {
+    pub sent: u64,
+    pub received: u64,
+}
+
+/// What the scenario handlers measure on a connection after the handshake.
+/// The driver combines it with the handshake latency into the
+/// [ScenarioMetrics] of the connection.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ConnectionMetrics {
+    /// When the first byte of application data was received
+    pub first_byte: Option<Instant>,
+    /// How long it took to exchange the application data of the scenario
+    pub transfer: Duration,
+    pub key_updates: Option<KeyUpdateCounts>,
+}
+
+impl ConnectionMetrics {
+    pub(crate) fn record_first_byte(&mut self) {
+        self.first_byte.get_or_insert_with(Instant::now);
+    }
+
+    /// The metrics of a connection which started its handshake at `start`,
+    /// and completed it after `handshake`.
+    pub fn scenario_metrics(
+        &self,
+        test: InteropTest,
+        parameters: TestParameters,
+        start: Instant,
+        handshake: Duration,
+    ) -> ScenarioMetrics {
+        ScenarioMetrics {
+            handshake: Some(handshake),
+            time_to_first_byte: self.first_byte.map(|first_byte| first_byte - start),
+            transfer: Some(self.transfer),
+            data_bytes: test
+                .transfers_data_volume()
+                .then(|| parameters.data_volume.total_bytes()),
+            key_updates_sent: self.key_updates.map(|counts| counts.sent),
+            key_updates_received: self.key_updates.map(|counts| counts.received),
+        }
+    }
+}
+
+/// Assert that a KeyUpdate was both sent and received on a connection. Not all
+/// implementations expose key update counters, in which case there is nothing
+/// to check.
+fn check_key_update_counts(
+    counts: Option<KeyUpdateCounts>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    match counts {
+        Some(counts) if counts.sent > 0 && counts.received > 0 => {
+            info!("key updates: {:?}", counts);
+            Ok(())
+        }
+        Some(counts) => {
+            error!("key update was not exchanged: {:?}", counts);
+            Err(InteropError::KeyUpdateNotExchanged(counts).into())
+        }
+        None => {
+            info!("key update counts are not available");
+            Ok(())
+        }
+    }
+}
+
+/// Assert that the expected ALPN protocol was selected, if the implementation
+/// reports the selected protocol.
+fn check_alpn(
+    test: InteropTest,
+    params: &NegotiatedParams,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let expected = test.expected_alpn_protocol();
+    match &params.alpn {
+        Some(selected) if selected.as_deref() != expected => {
+            error!("expected alpn protocol {:?}, selected {:?}", expected, selected);
+            Err(InteropError::AlpnMismatch {
+                expected,
+                selected: selected.clone(),
+            }
+            .into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Assert that the server name which was requested through SNI matches the
+/// expectations of the SNI scenarios, if the implementation reports it. Other
+/// scenarios don't make any assertions, because not all clients send SNI for
+/// the default server name.
+fn check_sni(
+    test: InteropTest,
+    params: &NegotiatedParams,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if !test.uses_alternate_certificate() {
+        return Ok(());
+    }
+    let expected = test.server_name();
+    match &params.sni {
+        Some(requested) if requested.as_deref() != expected => {
+            error!("expected server name {:?}, requested {:?}", expected, requested);
+            Err(InteropError::SniMismatch {
+                expected,
+                requested: requested.clone(),
+            }
+            .into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Assert that the key exchange group which is required by the scenario, or
+/// pinned by the runner, was negotiated, if the implementation reports it.
+fn check_group(
+    test: InteropTest,
+    parameters: TestParameters,
+    params: &NegotiatedParams,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let Some(expected) = parameters.group_for(test) else {
+        return Ok(());
+    };
+    match &params.key_exchange_group {
+        Some(negotiated) if !negotiated.eq_ignore_ascii_case(expected.iana_name()) => {
+            error!("expected key exchange group {}, negotiated {}", expected, negotiated);
+            Err(InteropError::GroupMismatch {
+                expected,
+                negotiated: negotiated.clone(),
+            }
+            .into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Check the outcome of a connection against the expectations of `test`.
+///
+/// Scenarios that are expected to fail succeed if the connection failed, either
+/// during the handshake or while exchanging application data.
+///
+/// `alert` extracts the TLS alert from a failure so that it can be reported to
+/// the runner, which is usually the `alert` method of the shim.
+pub fn check_outcome(
+    test: InteropTest,
+    outcome: Result<(), Box<dyn Error + Send + Sync>>,
+    alert: impl Fn(&(dyn Error + Send + Sync + 'static)) -> Option<TlsAlert>,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if let Some(alert) = outcome.as_ref().err().and_then(|e| alert(e.as_ref())) {
+        info!("{}", alert);
+    }
+    match (outcome, test.expects_handshake_failure()) {
+        (Err(e), true) => {
+            info!("connection {}: {}", EXPECTED_FAILURE_LOG, e);
+            Ok(())
+        }
+        (Ok(()), true) => {
+            error!("connection was expected to fail");
+            Err("connection unexpectedly succeeded".into())
+        }
+        (outcome, false) => outcome,
+    }
+}
+
+/// A connection that failed, along with the [ExitCode] that reports the
+/// failure.
+#[derive(Debug)]
+pub struct ConnectionFailure {
+    pub exit_code: ExitCode,
+    pub error: Box<dyn Error + Send + Sync>,
+}
+
+impl ConnectionFailure {
+    /// Classify the error of a failed connection. `alert` extracts the TLS
+    /// alert from the error, like in [check_outcome].
+    pub fn new(
+        error: Box<dyn Error + Send + Sync>,
+        handshake_complete: bool,
+        alert: impl Fn(&(dyn Error + Send + Sync + 'static)) -> Option<TlsAlert>,
+    ) -> Self {
+        let io_error = find_error::<io::Error>(error.as_ref());
+        let exit_code = if io_error.is_some_and(|e| e.kind() == io::ErrorKind::TimedOut) {
+            ExitCode::Timeout
+        } else if !handshake_complete || alert(error.as_ref()).is_some() {
+            ExitCode::HandshakeFailure
+        } else if let Some(e) = find_error::<InteropError>(error.as_ref()) {
+            e.exit_code()
+        } else if io_error.is_some() {
+            ExitCode::IoError
+        } else {
+            // everything else is one of the checks made by the shims
+            ExitCode::AssertionFailure
+        };
+        ConnectionFailure { exit_code, error }
+    }
+}
+
+impl Display for ConnectionFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.exit_code, self.error)
+    }
+}
+
+impl Error for ConnectionFailure {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(self.error.as_ref())
+    }
+}
+
+/// The ways in which a scenario can fail, other than the TLS implementation or
+/// the transport returning an error.
+#[derive(Debug)]
+pub enum InteropError {
+    /// The peer sent something other than the expected greeting, or the
+    /// acknowledgement of an upload.
+    GreetingMismatch {
+        expected: &'static str,
+        received: Vec<u8>,
+    },
+    /// The first byte of a MB of the large data scenarios wasn't the tag of its
+    /// block. With the `full` data volume each block is a GB.
+    TagMismatch {
+        block: u64,
+        mb: u64,
+        expected: u8,
+        received: u8,
+    },
+    /// The peer closed the connection while `reading` was still expected.
+    UnexpectedEof { reading: String },
+    /// The peer sent application data when it was expected to close the
+    /// connection.
+    UnexpectedData,
+    /// The handshake failed, with the error string of the TLS library and the
+    /// alert that was sent or received, if any.
+    Handshake {
+        error: String,
+        alert: Option<TlsAlert>,
+    },
+    /// The second connection of a resumption scenario did a full handshake.
+    ResumptionNotUsed,
+    /// The handshake of the hello retry scenario didn't include a
+    /// HelloRetryRequest.
+    HelloRetryNotUsed,
+    /// A KeyUpdate wasn't sent or received in a scenario that requires one.
+    KeyUpdateNotExchanged(KeyUpdateCounts),
+    /// A different ALPN protocol than the expected one was selected.
+    AlpnMismatch {
+        expected: Option<&'static str>,
+        selected: Option<String>,
+    },
+    /// The client requested a different server name than the expected one.
+    SniMismatch {
+        expected: Option<&'static str>,
+        requested: Option<String>,
+    },
+    /// A different key exchange group than the expected one was negotiated.
+    GroupMismatch {
+        expected: KeyExchangeGroup,
+        negotiated: String,
+    },
+    /// The generic handlers don't implement the scenario.
+    UnsupportedScenario(InteropTest),
+}
+
+impl InteropError {
+    /// The [ExitCode] that reports the error.
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            InteropError::UnexpectedEof { .. } => ExitCode::IoError,
+            InteropError::Handshake { .. } => ExitCode::HandshakeFailure,
+            _ => ExitCode::AssertionFailure,
+        }
+    }
+}
+
+impl Display for InteropError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InteropError::GreetingMismatch { expected, received } => write!(
+                f,
+                "expected {:?}, received {:?}",
+                expected,
+                String::from_utf8_lossy(received)
+            ),
+            InteropError::TagMismatch {
+                block,
+                mb,
+                expected,
+                received,
+            } => write!(
+                f,
+                "tag mismatch at block {}, MB {}: expected {}, received {}",
+                block, mb, expected, received
+            ),
+            InteropError::UnexpectedEof { reading } => {
+                write!(f, "connection closed while reading {}", reading)
+            }
+            InteropError::UnexpectedData => {
+                write!(f, "received data while waiting for the connection to close")
+            }
+            InteropError::Handshake { error, .. } => write!(f, "handshake failed: {}", error),
+            InteropError::ResumptionNotUsed => write!(f, "session resumption was not used"),
+            InteropError::HelloRetryNotUsed => write!(f, "hello retry request was not used"),
+            InteropError::KeyUpdateNotExchanged(counts) => {
+                write!(f, "key update was not exchanged: {:?}", counts)
+            }
+            InteropError::AlpnMismatch { expected, selected } => write!(
+                f,
+                "expected alpn protocol {:?}, selected {:?}",
+                expected, selected
+            ),
+            InteropError::SniMismatch {
+                expected,
+                requested,
+            } => write!(
+                f,
+                "expected server name {:?}, requested {:?}",
+                expected, requested
+            ),
+            InteropError::GroupMismatch {
+                expected,
+                negotiated,
+            } => write!(
+                f,
+                "expected key exchange group {}, negotiated {}",
+                expected, negotiated
+            ),
+            InteropError::UnsupportedScenario(test) => {
+                write!(f, "{:?} is not supported by the generic handler", test)
+            }
+        }
+    }
+}
+
+impl Error for InteropError {}
+
+/// Fill `buffer` from the stream. `reading` describes what is being read, for
+/// the error that is returned if the peer closes the connection early.
+async fn read_exact<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    buffer: &mut [u8],
+    reading: impl FnOnce() -> String,
+    metrics: &mut ConnectionMetrics,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut filled = 0;
+    if metrics.first_byte.is_none() {
+        // read_exact only returns once the whole buffer is filled
+        filled = stream.read(buffer).await?;
+        if filled > 0 {
+            metrics.record_first_byte();
+        }
+    }
+    match stream.read_exact(&mut buffer[filled..]).await {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+            Err(InteropError::UnexpectedEof { reading: reading() }.into())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Read a greeting, or the acknowledgement of an upload, and check that it
+/// matches `expected`.
+async fn read_greeting<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    expected: &'static str,
+    metrics: &mut ConnectionMetrics,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut buffer = vec![0; expected.len()];
+    read_exact(stream, &mut buffer, || format!("{:?}", expected), metrics).await?;
+    if buffer != expected.as_bytes() {
+        return Err(InteropError::GreetingMismatch {
+            expected,
+            received: buffer,
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Check that `received` is the tag of `block`, where `mb` is the offset of
+/// the MB within the block.
+pub(crate) fn check_tag(received: u8, block: u64, mb: u64) -> Result<(), InteropError> {
+    let expected = DataVolume::tag(block);
+    if received != expected {
+        return Err(InteropError::TagMismatch {
+            block,
+            mb,
+            expected,
+            received,
+        });
+    }
+    Ok(())
+}
+
+/// Read all of the blocks of the large data scenarios, checking the tag of
+/// each MB.
+async fn read_blocks<S: AsyncRead + Unpin>(
+    stream: &mut S,
+    volume: DataVolume,
+    metrics: &mut ConnectionMetrics,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut recv_buffer = vec![0; ONE_MB];
+    for block in 0..volume.blocks {
+        if block % 10 == 0 {
+            tracing::info!("blocks received: {}", block);
+        }
+        for mb in 0..volume.mb_per_block {
+            read_exact(
+                stream,
+                &mut recv_buffer,
+                || format!("block {}, MB {}", block, mb),
+                metrics,
+            )
+            .await?;
+            check_tag(recv_buffer[0], block, mb)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write all of the blocks of the large data scenarios. `send_key_update` is
+/// called before each block, which allows the key update scenarios to send a
+/// KeyUpdate with every block.
+pub(crate) async fn write_blocks<S, F>(
+    stream: &mut S,
+    volume: DataVolume,
+    mut send_key_update: F,
+) -> Result<(), Box<dyn Error + Send + Sync>>
+where
+    S: AsyncWrite + Unpin,
+    F: FnMut(&mut S) -> Result<(), Box<dyn Error + Send + Sync>>,
+{
+    let mut data_buffer = vec![0; ONE_MB];
+    for block in 0..volume.blocks {
+        send_key_update(stream)?;
+        if block % 10 == 0 {
+            tracing::info!("blocks sent: {}", block);
+        }
+        data_buffer[0] = DataVolume::tag(block);
+        for _ in 0..volume.mb_per_block {
+            stream.write_all(&data_buffer).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Find an error of type `E`, which TLS implementations return either directly
+/// or wrapped in an `io::Error` when it comes from an `AsyncRead` or `AsyncWrite`.
+pub fn find_error<'a, E: Error + 'static>(
+    error: &'a (dyn Error + Send + Sync + 'static),
+) -> Option<&'a E> {
+    error.downcast_ref::<E>().or_else(|| {
+        error
+            .downcast_ref::<io::Error>()
+            .and_then(io::Error::get_ref)
+            .and_then(|inner| inner.downcast_ref::<E>())
+    })
+}
 
 /// The ServerTLS trait allows for shared code between s2n-tls, rustls,
 /// and openssl. All of these TLS implementations have relatively similar API shapes
@@ -33,7 +507,10 @@ pub trait ServerTLS<T> {
     // the Stream is generic to allow for Turmoil test usage
     type Stream: Send + AsyncRead + AsyncWrite + Debug + Unpin;
 
-    fn get_server_config(test: InteropTest) -> Result<Option<Self::Config>, Box<dyn Error>>;
+    fn get_server_config(
+        test: InteropTest,
+        parameters: TestParameters,
+    ) -> Result<Option<Self::Config>, Box<dyn Error>>;
 
     fn acceptor(config: Self::Config) -> Self::Acceptor;
 
@@ -47,77 +524,144 @@ pub trait ServerTLS<T> {
     /// don't require any implementation specific apis.
     async fn handle_server_connection(
         test: InteropTest,
+        parameters: TestParameters,
         mut stream: Self::Stream,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+    ) -> Result<ConnectionMetrics, Box<dyn Error + Send + Sync>> {
         tracing::info!("Executing the {:?} scenario", test);
+        let params = Self::negotiated_params(&stream);
+        info!("{}", params);
+        check_alpn(test, &params)?;
+        check_sni(test, &params)?;
+        check_group(test, parameters, &params)?;
+        let volume = parameters.data_volume;
+        let start = Instant::now();
+        let mut metrics = ConnectionMetrics::default();
         match test {
             InteropTest::Handshake => {
                 // no application data exchange in the handshake case
             }
-            InteropTest::Greeting | InteropTest::MTLSRequestResponse => {
-                let mut client_greeting_buffer = vec![0; CLIENT_GREETING.as_bytes().len()];
-                stream.read_exact(&mut client_greeting_buffer).await?;
-                assert_eq!(client_greeting_buffer, CLIENT_GREETING.as_bytes());
+            InteropTest::Greeting
+            | InteropTest::MTLSRequestResponse
+            | InteropTest::SmallTcpPacket
+            | InteropTest::AlpnMatch
+            | InteropTest::AlpnServerPreference
+            | InteropTest::AlpnServerUnused
+            | InteropTest::SniSelection
+            | InteropTest::SniAbsent
+            // the negative scenarios only exchange data if the handshake
+            // unexpectedly succeeds
+            | InteropTest::AlpnNoOverlap
+            | InteropTest::UntrustedServerCa
+            | InteropTest::HostnameMismatch
+            | InteropTest::ExpiredServerCert
+            | InteropTest::MTLSMissingClientCert
+            | InteropTest::MTLSUntrustedClientCert
+            | InteropTest::RenegotiationRefusal
+            | InteropTest::PqHandshake => {
+                read_greeting(&mut stream, CLIENT_GREETING, &mut metrics).await?;
 
                 stream.write_all(SERVER_GREETING.as_bytes()).await?;
             }
-            InteropTest::LargeDataDownload => {
-                let mut client_greeting_buffer = vec![0; CLIENT_GREETING.as_bytes().len()];
-                stream.read_exact(&mut client_greeting_buffer).await?;
-                assert_eq!(client_greeting_buffer, CLIENT_GREETING.as_bytes());
-
-                let mut data_buffer = vec![0; ONE_MB];
-                // for each GB
-                for i in 0..LARGE_DATA_DOWNLOAD_GB {
-                    if i % 10 == 0 {
-                        tracing::info!("GB sent: {}", i);
-                    }
-                    data_buffer[0] = (i % u8::MAX as u64) as u8;
-                    for _ in 0..(ONE_GB / ONE_MB) {
-                        stream.write_all(&data_buffer).await?;
-                    }
-                }
+            // the server behavior is identical for the key update flood, the
+            // client is responsible for the flood of key updates
+            InteropTest::LargeDataDownload | InteropTest::KeyUpdateFlood => {
+                read_greeting(&mut stream, CLIENT_GREETING, &mut metrics).await?;
+                write_blocks(&mut stream, volume, |_| Ok(())).await?;
             }
             InteropTest::LargeDataDownloadWithFrequentKeyUpdates => {
-                Self::handle_large_data_download_with_frequent_key_updates(&mut stream).await?;
+                read_greeting(&mut stream, CLIENT_GREETING, &mut metrics).await?;
+                Self::handle_large_data_download_with_frequent_key_updates(&mut stream, volume)
+                    .await?;
             }
-            InteropTest::SessionResumption => {
-                let mut client_greeting_buffer = vec![0; CLIENT_GREETING.as_bytes().len()];
-                stream.read_exact(&mut client_greeting_buffer).await?;
-                assert_eq!(client_greeting_buffer, CLIENT_GREETING.as_bytes());
+            InteropTest::LargeDataUpload | InteropTest::LargeDataUploadWithFrequentKeyUpdates => {
+                read_blocks(&mut stream, volume, &mut metrics).await?;
 
+                // acknowledge that all of the data was received
                 stream.write_all(SERVER_GREETING.as_bytes()).await?;
-                if Self::validate_resumption(&stream) {
+            }
+            InteropTest::SessionResumption | InteropTest::SessionIdResumption => {
+                read_greeting(&mut stream, CLIENT_GREETING, &mut metrics).await?;
+
+                stream.write_all(SERVER_GREETING.as_bytes()).await?;
+                if Self::validate_resumption(&stream)? {
                     info!("session used session resumption")
                 } else {
                     error!("session resumption was not used");
-                    return Err("session resumption not used".into())
+                    return Err(InteropError::ResumptionNotUsed.into())
+                }
+            }
+            InteropTest::HelloRetryRequest => {
+                read_greeting(&mut stream, CLIENT_GREETING, &mut metrics).await?;
+
+                stream.write_all(SERVER_GREETING.as_bytes()).await?;
+                if Self::validate_hello_retry(&stream)? {
+                    info!("server sent a hello retry request")
+                } else {
+                    error!("hello retry request was not sent");
+                    return Err(InteropError::HelloRetryNotUsed.into())
                 }
             }
-            _ => panic!("Internal Framework Error"),
+            InteropTest::KeyUpdateRequest => {
+                // the client's KeyUpdate is received before the greeting
+                read_greeting(&mut stream, CLIENT_GREETING, &mut metrics).await?;
+
+                // the server's KeyUpdate response is sent before the greeting
+                stream.write_all(SERVER_GREETING.as_bytes()).await?;
+                check_key_update_counts(Self::key_update_counts(&stream))?;
+            }
+            _ => return Err(InteropError::UnsupportedScenario(test).into()),
         }
+        metrics.transfer = start.elapsed();
+        metrics.key_updates = Self::key_update_counts(&stream);
 
         tracing::info!("waiting for the client to close");
-        let wait_close = stream.read(&mut [0]).await?;
-        assert_eq!(wait_close, 0);
+        if stream.read(&mut [0]).await? != 0 {
+            error!("the client sent data instead of closing the connection");
+            return Err(InteropError::UnexpectedData.into());
+        }
 
         tracing::info!("closing the server side of connection");
         stream.shutdown().await?;
-        Ok(())
+        Ok(metrics)
     }
 
     /// If server supports the "large_data_download_forced_key_update" scenario, it should implement this method.
-    /// The method should *not* handle the shutdown of the stream. It should only handle the writing of application
-    /// messages and the sending of the key updates.
+    /// The method should *not* handle the client greeting or the shutdown of the stream. It should only handle the
+    /// writing of application messages and the sending of the key updates, which `write_blocks` does when
+    /// given a hook that sends a key update.
     async fn handle_large_data_download_with_frequent_key_updates(
         _stream: &mut Self::Stream,
+        _volume: DataVolume,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
         Err("unimplemented".into())
     }
 
     /// if the stream used resumption, then return true. Otherwise return false
-    fn validate_resumption(_stream: &Self::Stream) -> bool {
-        false
+    fn validate_resumption(_stream: &Self::Stream) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        Ok(false)
+    }
+
+    /// if the server sent a HelloRetryRequest during the handshake, then return
+    /// true. Otherwise return false
+    fn validate_hello_retry(_stream: &Self::Stream) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        Ok(false)
+    }
+
+    /// If the implementation exposes key update counters, return them.
+    fn key_update_counts(_stream: &Self::Stream) -> Option<KeyUpdateCounts> {
+        None
+    }
+
+    /// Return the parameters that were negotiated on the connection, as far as
+    /// the implementation exposes them.
+    fn negotiated_params(_stream: &Self::Stream) -> NegotiatedParams {
+        NegotiatedParams::default()
+    }
+
+    /// Return the TLS alert that was sent or received when the connection
+    /// failed with `error`, if the implementation exposes it.
+    fn alert(_error: &(dyn Error + Send + Sync + 'static)) -> Option<TlsAlert> {
+        None
     }
 }
 
@@ -126,44 +670,109 @@ pub trait ClientTLS<T> {
     type Connector: Clone + Send + 'static;
     type Stream: Send + AsyncRead + AsyncWrite + Debug + Unpin;
 
-    fn get_client_config(test: InteropTest) -> Result<Option<Self::Config>, Box<dyn Error>>;
+    fn get_client_config(
+        test: InteropTest,
+        parameters: TestParameters,
+    ) -> Result<Option<Self::Config>, Box<dyn Error>>;
 
     fn connector(config: Self::Config) -> Self::Connector;
 
+    /// Perform a handshake over `transport_stream`. The client sends `server_name`
+    /// in the SNI extension and authenticates the server with it. If `server_name`
+    /// is `None` the client must not send SNI, and authenticates the server as
+    /// [common::SERVER_NAME] instead.
     fn connect(
         client: &Self::Connector,
+        server_name: Option<&str>,
         transport_stream: T,
     ) -> impl std::future::Future<Output = Result<Self::Stream, Box<dyn Error + Send + Sync>>> + Send;
 
     async fn handle_client_connection(
         test: InteropTest,
+        parameters: TestParameters,
         mut stream: Self::Stream,
-    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+    ) -> Result<ConnectionMetrics, Box<dyn Error + Send + Sync>> {
         tracing::info!("executing the {:?} scenario", test);
+        let params = Self::negotiated_params(&stream);
+        info!("{}", params);
+        check_alpn(test, &params)?;
+        check_sni(test, &params)?;
+        check_group(test, parameters, &params)?;
+        let volume = parameters.data_volume;
+        let start = Instant::now();
+        let mut metrics = ConnectionMetrics::default();
         match test {
             InteropTest::Handshake => { /* no data exchange in the handshake case */ }
-            InteropTest::Greeting | InteropTest::MTLSRequestResponse | InteropTest::SessionResumption => {
+            InteropTest::Greeting
+            | InteropTest::MTLSRequestResponse
+            | InteropTest::SessionResumption
+            | InteropTest::SessionIdResumption
+            | InteropTest::PqHandshake
+            | InteropTest::SmallTcpPacket
+            | InteropTest::AlpnMatch
+            | InteropTest::AlpnServerPreference
+            | InteropTest::AlpnServerUnused
+            | InteropTest::SniSelection
+            | InteropTest::SniAbsent
+            // the negative scenarios only exchange data if the handshake
+            // unexpectedly succeeds
+            | InteropTest::AlpnNoOverlap
+            | InteropTest::UntrustedServerCa
+            | InteropTest::HostnameMismatch
+            | InteropTest::ExpiredServerCert
+            | InteropTest::MTLSMissingClientCert
+            | InteropTest::MTLSUntrustedClientCert => {
+                stream.write_all(CLIENT_GREETING.as_bytes()).await?;
+
+                read_greeting(&mut stream, SERVER_GREETING, &mut metrics).await?;
+            }
+            InteropTest::HelloRetryRequest => {
+                stream.write_all(CLIENT_GREETING.as_bytes()).await?;
+
+                read_greeting(&mut stream, SERVER_GREETING, &mut metrics).await?;
+
+                if Self::validate_hello_retry(&stream)? {
+                    info!("client received a hello retry request")
+                } else {
+                    error!("hello retry request was not received");
+                    return Err(InteropError::HelloRetryNotUsed.into())
+                }
+            }
+            InteropTest::KeyUpdateFlood => {
+                Self::handle_key_update_flood(&mut stream, volume, &mut metrics).await?;
+            }
+            InteropTest::KeyUpdateRequest => {
+                Self::request_key_update(&mut stream)?;
                 stream.write_all(CLIENT_GREETING.as_bytes()).await?;
 
-                let mut server_greeting_buffer = vec![0; SERVER_GREETING.as_bytes().len()];
-                stream.read_exact(&mut server_greeting_buffer).await?;
-                assert_eq!(server_greeting_buffer, SERVER_GREETING.as_bytes());
+                read_greeting(&mut stream, SERVER_GREETING, &mut metrics).await?;
+                check_key_update_counts(Self::key_update_counts(&stream))?;
             }
             InteropTest::LargeDataDownload
             | InteropTest::LargeDataDownloadWithFrequentKeyUpdates => {
                 stream.write_all(CLIENT_GREETING.as_bytes()).await?;
 
-                let mut recv_buffer = vec![0; ONE_MB];
-                for i in 0..LARGE_DATA_DOWNLOAD_GB {
-                    let tag = (i % u8::MAX as u64) as u8;
-                    for _ in 0..(ONE_GB / ONE_MB) {
-                        stream.read_exact(&mut recv_buffer).await?;
-                        assert_eq!(recv_buffer[0], tag);
-                    }
-                }
+                read_blocks(&mut stream, volume, &mut metrics).await?;
             }
-            _ => panic!("internal error, unrecognized client test {:?}", test),
+            InteropTest::LargeDataUpload => {
+                write_blocks(&mut stream, volume, |_| Ok(())).await?;
+                // the TLS implementation may still be buffering records that
+                // didn't fit in the transport's send window
+                stream.flush().await?;
+
+                read_greeting(&mut stream, SERVER_GREETING, &mut metrics).await?;
+            }
+            InteropTest::LargeDataUploadWithFrequentKeyUpdates => {
+                Self::handle_large_data_upload_with_frequent_key_updates(&mut stream, volume).await?;
+                stream.flush().await?;
+
+                read_greeting(&mut stream, SERVER_GREETING, &mut metrics).await?;
+            }
+            _ => return Err(InteropError::UnsupportedScenario(test).into()),
         }
+        metrics.transfer = start.elapsed();
+        metrics.key_updates = Self::key_update_counts(&stream);
+
         tracing::info!("shutting down the client side of the connection");
         stream.shutdown().await?;
 
@@ -175,6 +784,127 @@ pub trait ClientTLS<T> {
         // this method errors with a "ConnectionReset" error. Therefore we can't
         // assert errors on this method
         let _ = stream.read(&mut [0]).await;
-        Ok(())
+        Ok(metrics)
+    }
+
+    /// If client supports the "large_data_upload_with_frequent_key_updates" scenario, it should implement this method.
+    /// The method should *not* handle the shutdown of the stream or the server's acknowledgement. It should only
+    /// handle the writing of application messages and the sending of the key updates, which `write_blocks` does
+    /// when given a hook that sends a key update.
+    async fn handle_large_data_upload_with_frequent_key_updates(
+        _stream: &mut Self::Stream,
+        _volume: DataVolume,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Err("unimplemented".into())
+    }
+
+    /// if the client received a HelloRetryRequest during the handshake, then
+    /// return true. Otherwise return false
+    fn validate_hello_retry(_stream: &Self::Stream) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        Ok(false)
+    }
+
+    /// If client supports the "key_update_flood" scenario, it should implement this method.
+    /// The method should send the client greeting and read all of the server's data, sending
+    /// a flood of key updates in response to each record. It should record when the first
+    /// byte of data arrived in `metrics`, and should *not* handle the shutdown of the stream.
+    async fn handle_key_update_flood(
+        _stream: &mut Self::Stream,
+        _volume: DataVolume,
+        _metrics: &mut ConnectionMetrics,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Err("unimplemented".into())
+    }
+
+    /// If client supports the "key_update_request" scenario, it should implement this method.
+    /// The method should queue a KeyUpdate with `update_requested` set, which is sent before
+    /// the next application data record.
+    fn request_key_update(_stream: &mut Self::Stream) -> Result<(), Box<dyn Error + Send + Sync>> {
+        Err("unimplemented".into())
+    }
+
+    /// If the implementation exposes key update counters, return them.
+    fn key_update_counts(_stream: &Self::Stream) -> Option<KeyUpdateCounts> {
+        None
+    }
+
+    /// Return the parameters that were negotiated on the connection, as far as
+    /// the implementation exposes them.
+    fn negotiated_params(_stream: &Self::Stream) -> NegotiatedParams {
+        NegotiatedParams::default()
+    }
+
+    /// Return the TLS alert that was sent or received when the connection
+    /// failed with `error`, if the implementation exposes it.
+    fn alert(_error: &(dyn Error + Send + Sync + 'static)) -> Option<TlsAlert> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exit_code(error: Box<dyn Error + Send + Sync>) -> ExitCode {
+        ConnectionFailure::new(error, true, |_| None).exit_code
+    }
+
+    #[tokio::test]
+    async fn greeting_mismatch() {
+        let mut stream = SERVER_GREETING.as_bytes();
+        let error = read_greeting(&mut stream, CLIENT_GREETING, &mut Default::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(InteropError::GreetingMismatch { .. })
+        ));
+        assert_eq!(exit_code(error), ExitCode::AssertionFailure);
+    }
+
+    #[tokio::test]
+    async fn truncated_greeting() {
+        let mut stream = &CLIENT_GREETING.as_bytes()[..3];
+        let error = read_greeting(&mut stream, CLIENT_GREETING, &mut Default::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(InteropError::UnexpectedEof { .. })
+        ));
+        assert_eq!(exit_code(error), ExitCode::IoError);
+    }
+
+    #[tokio::test]
+    async fn tag_mismatch() {
+        let volume = DataVolume {
+            blocks: 2,
+            mb_per_block: 2,
+        };
+        // the second MB of the second block has the tag of the first block
+        let mut data = vec![0; 4 * ONE_MB];
+        data[2 * ONE_MB] = DataVolume::tag(1);
+        let error = read_blocks(&mut data.as_slice(), volume, &mut Default::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            error.downcast_ref(),
+            Some(InteropError::TagMismatch {
+                block: 1,
+                mb: 1,
+                ..
+            })
+        ));
+        assert_eq!(exit_code(error), ExitCode::AssertionFailure);
+    }
+
+    // e.g. the rustls client's key update flood, when the server stops reading
+    #[test]
+    fn timeout() {
+        let error = || io::Error::new(io::ErrorKind::TimedOut, "the connection deadlocked").into();
+        assert_eq!(exit_code(error()), ExitCode::Timeout);
+        // a timeout takes precedence over a failed handshake
+        let failure = ConnectionFailure::new(error(), false, |_| None);
+        assert_eq!(failure.exit_code, ExitCode::Timeout);
     }
 }
@@ -1,27 +1,146 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use common::{InteropTest, CLIENT_GREETING, LARGE_DATA_DOWNLOAD_GB};
-use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
+use common::{
+    AlertDescription, DataVolume, InteropTest, NegotiatedParams, TestParameters, TlsAlert,
+    TlsVersion,
+};
+use openssl::{
+    ex_data::Index,
+    ssl::{
+        AlpnError, ClientHelloResponse, NameType, Ssl, SslAcceptor, SslContext, SslFiletype,
+        SslMethod, SslOptions, SslVersion,
+    },
+};
 
-use std::{error::Error, pin::Pin};
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::{error::Error, fmt::Display, pin::Pin, sync::OnceLock};
+use tokio::io::{AsyncRead, AsyncWrite};
 
-use crate::{openssl_shim::ffi::ForeignWrapperTrait, ServerTLS, ONE_GB, ONE_MB};
+use crate::{openssl_shim::ffi::ForeignWrapperTrait, InteropError, KeyUpdateCounts, ServerTLS};
 
 pub struct OpensslShim;
 
+/// OpenSSL doesn't expose whether a HelloRetryRequest was sent, so the server
+/// counts the ClientHellos that it receives on a connection. A connection that
+/// received more than one ClientHello must have sent a HelloRetryRequest.
+fn client_hello_count_index() -> Index<Ssl, usize> {
+    static INDEX: OnceLock<Index<Ssl, usize>> = OnceLock::new();
+    *INDEX.get_or_init(|| Ssl::new_ex_index().unwrap())
+}
+
+/// OpenSSL doesn't expose key update counters, so a message callback counts
+/// the KeyUpdate messages that are sent and received on a connection.
+fn key_update_count_index() -> Index<Ssl, KeyUpdateCounts> {
+    static INDEX: OnceLock<Index<Ssl, KeyUpdateCounts>> = OnceLock::new();
+    *INDEX.get_or_init(|| Ssl::new_ex_index().unwrap())
+}
+
+/// The message callback also records the first fatal alert that is sent or
+/// received on a connection.
+fn alert_index() -> Index<Ssl, TlsAlert> {
+    static INDEX: OnceLock<Index<Ssl, TlsAlert>> = OnceLock::new();
+    *INDEX.get_or_init(|| Ssl::new_ex_index().unwrap())
+}
+
+/// Split a list of protocols in the ALPN wire format into the individual protocols
+fn alpn_protocols(mut wire: &[u8]) -> impl Iterator<Item = &[u8]> {
+    std::iter::from_fn(move || {
+        let (len, rest) = wire.split_first()?;
+        let (protocol, rest) = rest.split_at_checked(*len as usize)?;
+        wire = rest;
+        Some(protocol)
+    })
+}
+
 mod ffi {
-    use libc::c_int;
-    use openssl::{error::ErrorStack, ssl::SslRef};
-    use openssl_sys::SSL;
+    use common::{AlertDescription, TlsAlert};
+    use libc::{c_char, c_int, c_void, size_t};
+    use std::ffi::CStr;
+    use openssl::{
+        error::ErrorStack,
+        ssl::{SslContextBuilder, SslRef},
+    };
+    use openssl_sys::{SSL, SSL_CTX};
 
     // https://github.com/openssl/openssl/blob/6594baf6457c64f6fce3ec60cb2617f75d98d159/include/openssl/ssl.h.in#L995-L1000
     const SSL_KEY_UPDATE_NOT_REQUESTED: c_int = 0;
+    // https://github.com/openssl/openssl/blob/6594baf6457c64f6fce3ec60cb2617f75d98d159/include/openssl/ssl3.h#L218-L219
+    const SSL3_RT_ALERT: c_int = 21;
+    const SSL3_RT_HANDSHAKE: c_int = 22;
+    // https://github.com/openssl/openssl/blob/6594baf6457c64f6fce3ec60cb2617f75d98d159/include/openssl/ssl3.h#L270
+    const SSL3_AL_FATAL: u8 = 2;
+    // https://github.com/openssl/openssl/blob/6594baf6457c64f6fce3ec60cb2617f75d98d159/include/openssl/ssl3.h#L292
+    const SSL3_MT_KEY_UPDATE: u8 = 24;
+    // https://github.com/openssl/openssl/blob/6594baf6457c64f6fce3ec60cb2617f75d98d159/include/openssl/ssl.h.in#L1382
+    const SSL_CTRL_GET_NEGOTIATED_GROUP: c_int = 134;
+
+    type MsgCallback = unsafe extern "C" fn(
+        write_p: c_int,
+        version: c_int,
+        content_type: c_int,
+        buf: *const c_void,
+        len: size_t,
+        ssl: *mut SSL,
+        arg: *mut c_void,
+    );
 
     extern "C" {
         // https://www.openssl.org/docs/man1.1.1/man3/SSL_key_update.html
         pub fn SSL_key_update(s: *const SSL, updatetype: c_int) -> c_int;
+
+        // https://www.openssl.org/docs/man1.1.1/man3/SSL_CTX_set_msg_callback.html
+        fn SSL_CTX_set_msg_callback(ctx: *mut SSL_CTX, cb: Option<MsgCallback>);
+
+        // https://www.openssl.org/docs/man3.0/man3/SSL_group_to_name.html
+        fn SSL_group_to_name(s: *mut SSL, id: c_int) -> *const c_char;
+    }
+
+    /// Count the KeyUpdate messages and record the fatal alerts that are sent
+    /// and received on each connection created from `ctx`.
+    pub fn set_message_callback(ctx: &mut SslContextBuilder) {
+        unsafe { SSL_CTX_set_msg_callback(ctx.as_ptr(), Some(inspect_message)) }
+    }
+
+    unsafe extern "C" fn inspect_message(
+        write_p: c_int,
+        _version: c_int,
+        content_type: c_int,
+        buf: *const c_void,
+        len: size_t,
+        ssl: *mut SSL,
+        _arg: *mut c_void,
+    ) {
+        if len == 0 {
+            return;
+        }
+        let buf = std::slice::from_raw_parts(buf as *const u8, len);
+        let ssl = &mut *(ssl as *mut SslRef);
+        // TLS 1.3 handshake messages are reported after they are decrypted, so
+        // the first byte is the handshake message type
+        if content_type == SSL3_RT_HANDSHAKE && buf[0] == SSL3_MT_KEY_UPDATE {
+            let index = super::key_update_count_index();
+            let mut counts = ssl.ex_data(index).copied().unwrap_or_default();
+            if write_p == 1 {
+                counts.sent += 1;
+            } else {
+                counts.received += 1;
+            }
+            ssl.set_ex_data(index, counts);
+        }
+        // alerts are reported as the level followed by the description
+        if content_type == SSL3_RT_ALERT && buf.len() == 2 && buf[0] == SSL3_AL_FATAL {
+            let index = super::alert_index();
+            if ssl.ex_data(index).is_some() {
+                return;
+            }
+            let description = AlertDescription(buf[1]);
+            let alert = if write_p == 1 {
+                TlsAlert::Sent(description)
+            } else {
+                TlsAlert::Received(description)
+            };
+            ssl.set_ex_data(index, alert);
+        }
     }
 
     // https://github.com/sfackler/rust-openssl/blob/8e5d7bd402912ed3875dd8c4dcb510fc2f0c3686/openssl/src/lib.rs#L221C1-L227C2
@@ -40,6 +159,9 @@ mod ffi {
     pub trait ForeignWrapperTrait {
         fn key_update(&self) -> Result<(), ErrorStack>;
 
+        /// The name of the group that was used for the key exchange, e.g. "x25519"
+        fn negotiated_group(&self) -> Option<String>;
+
         fn as_ptr(&self) -> *mut SSL;
     }
 
@@ -55,6 +177,26 @@ mod ffi {
             Ok(())
         }
 
+        fn negotiated_group(&self) -> Option<String> {
+            unsafe {
+                // SSL_get_negotiated_group is a macro over SSL_ctrl
+                let group = openssl_sys::SSL_ctrl(
+                    self.as_ptr(),
+                    SSL_CTRL_GET_NEGOTIATED_GROUP,
+                    0,
+                    std::ptr::null_mut(),
+                );
+                if group <= 0 {
+                    return None;
+                }
+                let name = SSL_group_to_name(self.as_ptr(), group as c_int);
+                if name.is_null() {
+                    return None;
+                }
+                Some(CStr::from_ptr(name).to_string_lossy().into_owned())
+            }
+        }
+
         fn as_ptr(&self) -> *mut SSL {
             self as *const openssl::ssl::SslRef as *mut openssl_sys::SSL
         }
@@ -72,20 +214,89 @@ impl<T: AsyncRead + AsyncWrite + Unpin + Send + core::fmt::Debug> ServerTLS<T> f
     type Acceptor = openssl::ssl::SslAcceptor;
     type Stream = tokio_openssl::SslStream<T>;
 
-    fn get_server_config(test: InteropTest) -> Result<Option<Self::Config>, Box<dyn Error>> {
-        let mut acceptor = SslAcceptor::mozilla_modern_v5(SslMethod::tls()).unwrap();
-        acceptor.set_private_key_file(
-            common::pem_file_path(common::PemType::ServerKey),
-            SslFiletype::PEM,
-        )?;
-        acceptor.set_certificate_chain_file(common::pem_file_path(common::PemType::ServerChain))?;
-        if test == InteropTest::MTLSRequestResponse {
+    fn get_server_config(
+        test: InteropTest,
+        parameters: TestParameters,
+    ) -> Result<Option<Self::Config>, Box<dyn Error>> {
+        let mut acceptor = match parameters.tls_version {
+            TlsVersion::Tls13 => SslAcceptor::mozilla_modern_v5(SslMethod::tls())?,
+            TlsVersion::Tls12 => {
+                let mut acceptor = SslAcceptor::mozilla_intermediate_v5(SslMethod::tls())?;
+                acceptor.set_max_proto_version(Some(SslVersion::TLS1_2))?;
+                acceptor
+            }
+        };
+        if let Some(cipher_suite) = parameters.cipher_suite {
+            // OpenSSL uses the IANA names for TLS 1.3 cipher suites
+            acceptor.set_ciphersuites(cipher_suite.iana_name())?;
+        }
+        if let Some(group) = parameters.group_for(test) {
+            // the hybrid groups require OpenSSL 3.5+
+            if group.is_hybrid() && openssl::version::number() < 0x3050_0000 {
+                return Ok(None);
+            }
+            acceptor.set_groups_list(group.iana_name())?;
+        }
+        let (chain, key) = test.server_certificate();
+        acceptor.set_private_key_file(common::pem_file_path(key), SslFiletype::PEM)?;
+        acceptor.set_certificate_chain_file(common::pem_file_path(chain))?;
+        if test.requires_client_auth() {
             acceptor.set_ca_file(common::pem_file_path(common::PemType::CaCert))?;
             acceptor.set_verify(
                 openssl::ssl::SslVerifyMode::FAIL_IF_NO_PEER_CERT
                     | openssl::ssl::SslVerifyMode::PEER,
             );
         }
+        if test.uses_alternate_certificate() {
+            let mut alternate = SslContext::builder(SslMethod::tls())?;
+            alternate.set_private_key_file(
+                common::pem_file_path(common::PemType::AlternateServerKey),
+                SslFiletype::PEM,
+            )?;
+            alternate.set_certificate_chain_file(common::pem_file_path(
+                common::PemType::AlternateServerChain,
+            ))?;
+            let alternate = alternate.build();
+            // switch to the alternate certificate if its name is requested,
+            // otherwise the default certificate is used
+            acceptor.set_servername_callback(move |ssl, _alert| {
+                if ssl.servername(NameType::HOST_NAME) == Some(common::ALTERNATE_SERVER_NAME) {
+                    ssl.set_ssl_context(&alternate)
+                        .map_err(|_| openssl::ssl::SniError::ALERT_FATAL)?;
+                }
+                Ok(())
+            });
+        }
+        if test == InteropTest::SessionIdResumption {
+            // without tickets, sessions are resumed from the server's session
+            // cache, which is enabled by default
+            acceptor.set_options(SslOptions::NO_TICKET);
+        }
+        // OpenSSL 3 refuses client initiated renegotiation by default, so the
+        // renegotiation refusal scenario doesn't require any configuration
+        if test == InteropTest::HelloRetryRequest {
+            // clients don't send a P-384 key share by default, so this forces
+            // a HelloRetryRequest
+            acceptor.set_groups_list("P-384")?;
+        }
+        let server_protocols = test.server_alpn_protocols();
+        if !server_protocols.is_empty() {
+            // select the most preferred server protocol that the client offered
+            acceptor.set_alpn_select_callback(move |_ssl, client_protocols| {
+                server_protocols
+                    .iter()
+                    .map(|protocol| protocol.as_bytes())
+                    .find(|protocol| alpn_protocols(client_protocols).any(|p| p == *protocol))
+                    .ok_or(AlpnError::ALERT_FATAL)
+            });
+        }
+        acceptor.set_client_hello_callback(|ssl, _alert| {
+            let index = client_hello_count_index();
+            let count = ssl.ex_data(index).copied().unwrap_or(0);
+            ssl.set_ex_data(index, count + 1);
+            Ok(ClientHelloResponse::SUCCESS)
+        });
+        ffi::set_message_callback(&mut acceptor);
         Ok(Some(acceptor))
     }
 
@@ -97,33 +308,91 @@ impl<T: AsyncRead + AsyncWrite + Unpin + Send + core::fmt::Debug> ServerTLS<T> f
         server: &Self::Acceptor,
         transport_stream: T,
     ) -> Result<Self::Stream, Box<dyn Error + Send + Sync>> {
-        let ssl = openssl::ssl::Ssl::new(server.context()).unwrap();
-        let mut ssl_stream = Self::Stream::new(ssl, transport_stream)?;
-        Pin::new(&mut ssl_stream).accept().await.unwrap();
+        let handshake_error = |error: &dyn Display, alert| InteropError::Handshake {
+            error: error.to_string(),
+            alert,
+        };
+        let ssl =
+            openssl::ssl::Ssl::new(server.context()).map_err(|e| handshake_error(&e, None))?;
+        let mut ssl_stream =
+            Self::Stream::new(ssl, transport_stream).map_err(|e| handshake_error(&e, None))?;
+        if let Err(error) = Pin::new(&mut ssl_stream).accept().await {
+            // the connection is dropped when the handshake fails, so the error
+            // carries the alert that the message callback recorded
+            let alert = ssl_stream.ssl().ex_data(alert_index()).copied();
+            return Err(handshake_error(&error, alert).into());
+        }
         Ok(ssl_stream)
     }
 
     async fn handle_large_data_download_with_frequent_key_updates(
         stream: &mut Self::Stream,
+        volume: DataVolume,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        tracing::info!("waiting for client greeting");
-        let mut server_greeting_buffer = vec![0; CLIENT_GREETING.as_bytes().len()];
-        stream.read_exact(&mut server_greeting_buffer).await?;
-        assert_eq!(server_greeting_buffer, CLIENT_GREETING.as_bytes());
-
-        let mut data_buffer = vec![0; ONE_MB];
-        for i in 0..LARGE_DATA_DOWNLOAD_GB {
-            // send a key update with each gigabyte
-            stream.ssl().key_update()?;
-            if i % 10 == 0 {
-                tracing::info!("GB sent: {}", i);
-            }
-            data_buffer[0] = (i % u8::MAX as u64) as u8;
-            for _ in 0..(ONE_GB / ONE_MB) {
-                stream.write_all(&data_buffer).await?;
-            }
+        // send a key update with each block
+        crate::write_blocks(stream, volume, |stream| Ok(stream.ssl().key_update()?)).await
+    }
+
+    fn validate_resumption(stream: &Self::Stream) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        Ok(stream.ssl().session_reused())
+    }
+
+    fn validate_hello_retry(stream: &Self::Stream) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        Ok(stream
+            .ssl()
+            .ex_data(client_hello_count_index())
+            .is_some_and(|count| *count > 1))
+    }
+
+    fn negotiated_params(stream: &Self::Stream) -> NegotiatedParams {
+        let ssl = stream.ssl();
+        NegotiatedParams {
+            protocol_version: Some(ssl.version_str().to_owned()),
+            cipher_suite: ssl
+                .current_cipher()
+                .and_then(|cipher| cipher.standard_name())
+                .map(str::to_owned),
+            key_exchange_group: ssl.negotiated_group(),
+            // the negotiated signature scheme is only exposed by OpenSSL 3.5+
+            signature_scheme: None,
+            alpn: Some(
+                ssl.selected_alpn_protocol()
+                    .map(|protocol| String::from_utf8_lossy(protocol).into_owned()),
+            ),
+            sni: Some(ssl.servername(NameType::HOST_NAME).map(str::to_owned)),
+            resumed: Some(ssl.session_reused()),
+            hello_retry: Self::validate_hello_retry(stream).ok(),
         }
+    }
 
-        Ok(())
+    fn key_update_counts(stream: &Self::Stream) -> Option<KeyUpdateCounts> {
+        Some(
+            stream
+                .ssl()
+                .ex_data(key_update_count_index())
+                .copied()
+                .unwrap_or_default(),
+        )
+    }
+
+    fn alert(error: &(dyn Error + Send + Sync + 'static)) -> Option<TlsAlert> {
+        if let Some(InteropError::Handshake { alert, .. }) =
+            crate::find_error::<InteropError>(error)
+        {
+            return *alert;
+        }
+        // after the handshake, a received alert is only visible in the error
+        // stack, where the reason code is the alert description offset by 1000
+        // https://github.com/openssl/openssl/blob/6594baf6457c64f6fce3ec60cb2617f75d98d159/include/openssl/ssl.h.in#L1132
+        const SSL_AD_REASON_OFFSET: i32 = 1000;
+        crate::find_error::<openssl::ssl::Error>(error)?
+            .ssl_error()?
+            .errors()
+            .iter()
+            .find_map(|e| {
+                let description = e.reason_code().checked_sub(SSL_AD_REASON_OFFSET)?;
+                let description = AlertDescription(description.try_into().ok()?);
+                Some(TlsAlert::Received(description))
+            })
     }
 }
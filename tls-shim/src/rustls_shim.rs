@@ -3,22 +3,37 @@
 
 use std::{
     fmt::{Debug, Display},
-    io::BufReader,
+    io::{self, BufReader},
     sync::Arc,
+    time::Duration,
 };
 
-use common::InteropTest;
+use common::{
+    AlertDescription, CipherSuite, DataVolume, InteropTest, KeyExchangeGroup, NegotiatedParams,
+    TestParameters, TlsAlert, TlsVersion, CLIENT_GREETING,
+};
 use rustls_pemfile::pkcs8_private_keys;
-use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio_rustls::{
     rustls::{
         self,
         pki_types::{self, PrivateKeyDer},
+        HandshakeKind, ProtocolVersion,
     },
     TlsConnector,
 };
 
-use crate::ClientTLS;
+use crate::{check_tag, ClientTLS, ConnectionMetrics, InteropError, ONE_MB};
+
+/// The maximum amount of plaintext in a single TLS record
+const MAX_RECORD_PLAINTEXT: usize = 16_384;
+/// The number of key updates that are sent in response to each record in the
+/// key update flood scenario. The JDK sends a single key update per record, but
+/// that takes hundreds of Gb of data to exhaust the TCP flow control window.
+const KEY_UPDATES_PER_RECORD: usize = 1_000;
+/// If the key updates can't be written within this duration, then the peer
+/// has stopped reading and the connection is deadlocked.
+const KEY_UPDATE_FLOOD_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub struct RustlsShim;
 
@@ -35,6 +50,7 @@ impl<T: AsyncRead + AsyncWrite + Unpin + Send + Debug> ClientTLS<T> for RustlsSh
 
     fn get_client_config(
         test: common::InteropTest,
+        parameters: TestParameters,
     ) -> Result<Option<Self::Config>, Box<dyn std::error::Error>> {
         let mut root_store = rustls::RootCertStore::empty();
 
@@ -45,36 +61,86 @@ impl<T: AsyncRead + AsyncWrite + Unpin + Send + Debug> ClientTLS<T> for RustlsSh
             .unwrap()
             .unwrap();
         root_store.add(root_cert).unwrap();
+        let versions: &[&rustls::SupportedProtocolVersion] = match parameters.tls_version {
+            TlsVersion::Tls12 => &[&rustls::version::TLS12],
+            TlsVersion::Tls13 => &[&rustls::version::TLS13],
+        };
+        // restrict the default provider to the pinned algorithms
+        let mut provider = rustls::crypto::aws_lc_rs::default_provider();
+        if let Some(cipher_suite) = parameters.cipher_suite {
+            let cipher_suite = match cipher_suite {
+                CipherSuite::Aes128GcmSha256 => rustls::CipherSuite::TLS13_AES_128_GCM_SHA256,
+                CipherSuite::Aes256GcmSha384 => rustls::CipherSuite::TLS13_AES_256_GCM_SHA384,
+                CipherSuite::Chacha20Poly1305Sha256 => {
+                    rustls::CipherSuite::TLS13_CHACHA20_POLY1305_SHA256
+                }
+            };
+            provider.cipher_suites.retain(|suite| suite.suite() == cipher_suite);
+        }
+        if let Some(group) = parameters.group_for(test) {
+            let group = match group {
+                KeyExchangeGroup::X25519 => rustls::NamedGroup::X25519,
+                KeyExchangeGroup::Secp256r1 => rustls::NamedGroup::secp256r1,
+                KeyExchangeGroup::Secp384r1 => rustls::NamedGroup::secp384r1,
+                KeyExchangeGroup::X25519Mlkem768 => rustls::NamedGroup::X25519MLKEM768,
+            };
+            provider.kx_groups.retain(|kx_group| kx_group.name() == group);
+        }
+        let builder = rustls::ClientConfig::builder_with_provider(Arc::new(provider))
+            .with_protocol_versions(versions)?;
 
-        let config = match test {
+        let mut config = match test {
             InteropTest::Greeting
             | InteropTest::Handshake
             | InteropTest::SessionResumption
+            | InteropTest::SessionIdResumption
+            | InteropTest::PqHandshake
+            | InteropTest::HelloRetryRequest
+            | InteropTest::SmallTcpPacket
             | InteropTest::LargeDataDownload
-            | InteropTest::LargeDataDownloadWithFrequentKeyUpdates => {
-                rustls::ClientConfig::builder()
+            | InteropTest::LargeDataDownloadWithFrequentKeyUpdates
+            | InteropTest::LargeDataUpload
+            | InteropTest::LargeDataUploadWithFrequentKeyUpdates
+            | InteropTest::KeyUpdateRequest
+            | InteropTest::KeyUpdateFlood
+            | InteropTest::AlpnMatch
+            | InteropTest::AlpnServerPreference
+            | InteropTest::AlpnNoOverlap
+            | InteropTest::AlpnServerUnused
+            | InteropTest::SniSelection
+            | InteropTest::SniAbsent
+            | InteropTest::UntrustedServerCa
+            | InteropTest::HostnameMismatch
+            | InteropTest::ExpiredServerCert
+            | InteropTest::MTLSMissingClientCert => {
+                builder
                     .with_root_certificates(root_store)
                     .with_no_client_auth()
             }
-            InteropTest::MTLSRequestResponse => {
-                let mut chain_reader = BufReader::new(std::fs::File::open(common::pem_file_path(
-                    common::PemType::ClientChain,
-                ))?);
+            InteropTest::MTLSRequestResponse | InteropTest::MTLSUntrustedClientCert => {
+                let (chain, key) = test.client_certificate().unwrap();
+                let mut chain_reader =
+                    BufReader::new(std::fs::File::open(common::pem_file_path(chain))?);
                 let client_chain = rustls_pemfile::certs(&mut chain_reader)
                     .map(|maybe_cert| maybe_cert.unwrap())
                     .collect();
 
-                let mut key_reader = BufReader::new(std::fs::File::open(common::pem_file_path(
-                    common::PemType::ClientKey,
-                ))?);
+                let mut key_reader =
+                    BufReader::new(std::fs::File::open(common::pem_file_path(key))?);
                 let client_key = pkcs8_private_keys(&mut key_reader).next().unwrap()?;
                 let client_key = PrivateKeyDer::Pkcs8(client_key);
-                rustls::ClientConfig::builder()
+                builder
                     .with_root_certificates(root_store)
                     .with_client_auth_cert(client_chain, client_key)?
             }
             _ => return Ok(None),
         };
+        config.alpn_protocols = test
+            .client_alpn_protocols()
+            .iter()
+            .map(|protocol| protocol.as_bytes().to_vec())
+            .collect();
+        config.enable_sni = test.server_name().is_some();
 
         Ok(Some(Arc::new(config)))
     }
@@ -85,10 +151,168 @@ impl<T: AsyncRead + AsyncWrite + Unpin + Send + Debug> ClientTLS<T> for RustlsSh
 
     async fn connect(
         client: &Self::Connector,
+        server_name: Option<&str>,
         transport_stream: T,
     ) -> Result<Self::Stream, Box<dyn std::error::Error + Send + Sync>> {
-        let domain = "localhost";
-        let server_name = pki_types::ServerName::try_from(domain)?;
-        Ok(client.connect(server_name, transport_stream).await?)
+        // SNI is disabled in the config if there is no server name, but rustls
+        // still needs a name to authenticate the server
+        let server_name = server_name.unwrap_or(common::SERVER_NAME).to_owned();
+        let server_name = pki_types::ServerName::try_from(server_name)?;
+        match client.connect(server_name, transport_stream).await {
+            Ok(stream) => Ok(stream),
+            Err(error) => {
+                let alert = <Self as ClientTLS<T>>::alert(&error);
+                Err(InteropError::Handshake {
+                    error: error.to_string(),
+                    alert,
+                }
+                .into())
+            }
+        }
+    }
+
+    async fn handle_large_data_upload_with_frequent_key_updates(
+        stream: &mut Self::Stream,
+        volume: DataVolume,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // the KeyUpdate message is queued, and is sent before the next
+        // application data record
+        crate::write_blocks(stream, volume, |stream| {
+            Ok(stream.get_mut().1.refresh_traffic_keys()?)
+        })
+        .await
+    }
+
+    fn validate_hello_retry(
+        stream: &Self::Stream,
+    ) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(stream.get_ref().1.handshake_kind() == Some(HandshakeKind::FullWithHelloRetryRequest))
+    }
+
+    async fn handle_key_update_flood(
+        stream: &mut Self::Stream,
+        volume: DataVolume,
+        metrics: &mut ConnectionMetrics,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        stream.write_all(CLIENT_GREETING.as_bytes()).await?;
+
+        // read (at most) a single record at a time, so that key updates are
+        // sent in response to each record like the JDK
+        let mut record_buffer = vec![0; MAX_RECORD_PLAINTEXT];
+        let total = volume.total_mb() * ONE_MB as u64;
+        let mut received = 0;
+        while received < total {
+            let read = stream.read(&mut record_buffer).await?;
+            if read == 0 {
+                return Err(InteropError::UnexpectedEof {
+                    reading: format!("the data after {} bytes", received),
+                }
+                .into());
+            }
+            metrics.record_first_byte();
+
+            // check the tag at the start of each MB in the record
+            let end = received + read as u64;
+            let mut mb_start = received.next_multiple_of(ONE_MB as u64);
+            while mb_start < end {
+                let mb = mb_start / ONE_MB as u64;
+                check_tag(
+                    record_buffer[(mb_start - received) as usize],
+                    mb / volume.mb_per_block,
+                    mb % volume.mb_per_block,
+                )?;
+                mb_start += ONE_MB as u64;
+            }
+            received = end;
+
+            for _ in 0..KEY_UPDATES_PER_RECORD {
+                stream.get_mut().1.refresh_traffic_keys()?;
+            }
+            // like the JDK, don't read any more data until the key updates
+            // have been written
+            tokio::time::timeout(KEY_UPDATE_FLOOD_TIMEOUT, stream.flush())
+                .await
+                .map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!(
+                            "key update flood deadlocked the connection after {} bytes",
+                            received
+                        ),
+                    )
+                })??;
+        }
+        Ok(())
+    }
+
+    fn negotiated_params(stream: &Self::Stream) -> NegotiatedParams {
+        let connection = stream.get_ref().1;
+        let protocol_version = connection.protocol_version().map(|version| match version {
+            ProtocolVersion::TLSv1_3 => "TLSv1.3".to_owned(),
+            ProtocolVersion::TLSv1_2 => "TLSv1.2".to_owned(),
+            other => format!("{:?}", other),
+        });
+        // rustls names TLS 1.3 cipher suites with a "TLS13_" prefix, rather
+        // than the IANA "TLS_" prefix
+        let cipher_suite = connection.negotiated_cipher_suite().map(|suite| {
+            let name = format!("{:?}", suite.suite());
+            match name.strip_prefix("TLS13_") {
+                Some(name) => format!("TLS_{}", name),
+                None => name,
+            }
+        });
+        NegotiatedParams {
+            protocol_version,
+            cipher_suite,
+            key_exchange_group: connection
+                .negotiated_key_exchange_group()
+                .map(|group| format!("{:?}", group.name())),
+            // rustls doesn't expose the signature scheme that the server used
+            signature_scheme: None,
+            alpn: Some(
+                connection
+                    .alpn_protocol()
+                    .map(|protocol| String::from_utf8_lossy(protocol).into_owned()),
+            ),
+            // rustls doesn't expose whether the client sent SNI
+            sni: None,
+            resumed: connection
+                .handshake_kind()
+                .map(|kind| kind == HandshakeKind::Resumed),
+            hello_retry: connection
+                .handshake_kind()
+                .map(|kind| kind == HandshakeKind::FullWithHelloRetryRequest),
+        }
+    }
+
+    fn alert(error: &(dyn std::error::Error + Send + Sync + 'static)) -> Option<TlsAlert> {
+        if let Some(InteropError::Handshake { alert, .. }) =
+            crate::find_error::<InteropError>(error)
+        {
+            return *alert;
+        }
+        let description = |description| AlertDescription(u8::from(description));
+        match crate::find_error::<rustls::Error>(error)? {
+            rustls::Error::AlertReceived(received) => {
+                Some(TlsAlert::Received(description(*received)))
+            }
+            // rustls sends an alert for each of these errors
+            rustls::Error::InvalidCertificate(e) => {
+                Some(TlsAlert::Sent(description(e.clone().into())))
+            }
+            rustls::Error::InvalidMessage(e) => Some(TlsAlert::Sent(description((*e).into()))),
+            rustls::Error::NoApplicationProtocol => Some(TlsAlert::Sent(description(
+                rustls::AlertDescription::NoApplicationProtocol,
+            ))),
+            _ => None,
+        }
+    }
+
+    fn request_key_update(
+        stream: &mut Self::Stream,
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        // rustls always sets `update_requested` on the KeyUpdate message
+        stream.get_mut().1.refresh_traffic_keys()?;
+        Ok(())
     }
 }
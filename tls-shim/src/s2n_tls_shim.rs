@@ -1,26 +1,228 @@
 // Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-use common::{InteropTest, CLIENT_GREETING, LARGE_DATA_DOWNLOAD_GB};
-use s2n_tls::{callbacks::{ConnectionFuture, SessionTicketCallback}, config::{Config, ConnectionInitializer}, security::DEFAULT_TLS13};
+use common::{AlertDescription, CipherSuite, DataVolume, InteropTest, KeyExchangeGroup, NegotiatedParams, TestParameters, TlsAlert, TlsVersion};
+use s2n_tls::{cert_chain, callbacks::{ConnectionFuture, SessionTicketCallback, VerifyHostNameCallback}, config::{Config, ConnectionInitializer}, connection::{Builder, Connection}, enums::{ClientAuthType, Mode, Version}, security::{Policy, DEFAULT_TLS13}};
 use tracing::{debug, info};
 
-use std::{alloc::System, cell::RefCell, error::Error, pin::Pin, sync::{Arc, Mutex}, time::SystemTime};
-use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use std::{error::Error, pin::Pin, sync::{Arc, Mutex}, time::SystemTime};
+use tokio::io::{AsyncRead, AsyncWrite};
 
-use crate::{ClientTLS, ServerTLS};
+use crate::{ClientTLS, InteropError, KeyUpdateCounts, ServerTLS};
 
 const STEK_NAME: &[u8; 9] = b"test_stek";
 const STEK_VALUE: [u8; 19] = [3,1,4,1,5,9,2,6,5,3,5,8,9,7,9,3,2,4,6];
+/// A TLS 1.3 only security policy where secp384r1 is the only supported group,
+/// and TLS_AES_256_GCM_SHA384 is the only cipher suite. Clients don't send a
+/// P-384 key share by default, so this forces a HelloRetryRequest.
+const P384_ONLY_POLICY: &str = "20250414";
+/// A TLS 1.2 only security policy with ECDHE cipher suites, used when the
+/// peers are restricted to TLS 1.2.
+const TLS12_POLICY: &str = "20240501";
+/// A security policy which prefers the X25519MLKEM768 hybrid group. s2n-tls
+/// doesn't have a policy where a hybrid group is the only group, so the policy
+/// also supports classical groups and the negotiated group is asserted instead.
+const PQ_POLICY: &str = "default_pq";
+
+/// s2n-tls names TLS 1.2 cipher suites with the OpenSSL names, while the other
+/// implementations report the IANA names. These are the cipher suites in
+/// [`TLS12_POLICY`].
+const TLS12_CIPHER_SUITE_NAMES: [(&str, &str); 8] = [
+    ("ECDHE-ECDSA-AES128-GCM-SHA256", "TLS_ECDHE_ECDSA_WITH_AES_128_GCM_SHA256"),
+    ("ECDHE-ECDSA-AES256-GCM-SHA384", "TLS_ECDHE_ECDSA_WITH_AES_256_GCM_SHA384"),
+    ("ECDHE-ECDSA-AES128-SHA256", "TLS_ECDHE_ECDSA_WITH_AES_128_CBC_SHA256"),
+    ("ECDHE-ECDSA-AES256-SHA384", "TLS_ECDHE_ECDSA_WITH_AES_256_CBC_SHA384"),
+    ("ECDHE-RSA-AES128-GCM-SHA256", "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256"),
+    ("ECDHE-RSA-AES256-GCM-SHA384", "TLS_ECDHE_RSA_WITH_AES_256_GCM_SHA384"),
+    ("ECDHE-RSA-AES128-SHA256", "TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA256"),
+    ("ECDHE-RSA-AES256-SHA384", "TLS_ECDHE_RSA_WITH_AES_256_CBC_SHA384"),
+];
 
 pub struct S2NShim;
 
+/// s2n-tls connections expose the same information for clients and servers
+fn negotiated_params(connection: &Connection) -> NegotiatedParams {
+    let protocol_version = connection.actual_protocol_version().ok().map(|version| {
+        match version {
+            Version::TLS13 => "TLSv1.3".to_owned(),
+            Version::TLS12 => "TLSv1.2".to_owned(),
+            Version::TLS11 => "TLSv1.1".to_owned(),
+            Version::TLS10 => "TLSv1".to_owned(),
+            other => format!("{:?}", other),
+        }
+    });
+    NegotiatedParams {
+        protocol_version,
+        cipher_suite: connection.cipher_suite().ok().map(|name| {
+            TLS12_CIPHER_SUITE_NAMES
+                .iter()
+                .find(|(openssl_name, _)| *openssl_name == name)
+                .map_or(name, |(_, iana_name)| iana_name)
+                .to_owned()
+        }),
+        key_exchange_group: connection.selected_key_exchange_group().map(str::to_owned),
+        signature_scheme: connection.signature_scheme().map(str::to_owned),
+        alpn: Some(
+            connection
+                .application_protocol()
+                .map(|protocol| String::from_utf8_lossy(protocol).into_owned()),
+        ),
+        sni: Some(connection.server_name().map(str::to_owned)),
+        resumed: Some(connection.resumed()),
+        hello_retry: connection
+            .handshake_type()
+            .ok()
+            .map(|handshake| handshake.contains("HELLO_RETRY_REQUEST")),
+    }
+}
+
+/// Builds connections that record the alert that the peer sent when they are
+/// dropped. s2n-tls-tokio drops the connection of a failed handshake, so the
+/// alert can't be read from the connection afterwards.
+#[derive(Clone)]
+struct AlertRecorder {
+    config: Config,
+    alert: Arc<Mutex<Option<u8>>>,
+}
+
+impl AlertRecorder {
+    fn new(config: &Config) -> Self {
+        AlertRecorder { config: config.clone(), alert: Arc::default() }
+    }
+
+    /// Wrap the error of a failed handshake, with the alert that the peer sent
+    fn handshake_error(&self, error: s2n_tls::error::Error) -> Box<dyn Error + Send + Sync> {
+        let alert = self.alert.lock().unwrap().map(|alert| TlsAlert::Received(AlertDescription(alert)));
+        InteropError::Handshake { error: error.to_string(), alert }.into()
+    }
+}
+
+impl Builder for AlertRecorder {
+    type Output = AlertRecordingConnection;
+
+    fn build_connection(&self, mode: Mode) -> Result<Self::Output, s2n_tls::error::Error> {
+        Ok(AlertRecordingConnection {
+            connection: self.config.build_connection(mode)?,
+            alert: self.alert.clone(),
+        })
+    }
+}
+
+/// A connection which records the alert that the peer sent when it is dropped
+pub struct AlertRecordingConnection {
+    connection: Connection,
+    alert: Arc<Mutex<Option<u8>>>,
+}
+
+impl AsRef<Connection> for AlertRecordingConnection {
+    fn as_ref(&self) -> &Connection {
+        &self.connection
+    }
+}
+
+impl AsMut<Connection> for AlertRecordingConnection {
+    fn as_mut(&mut self) -> &mut Connection {
+        &mut self.connection
+    }
+}
+
+impl Drop for AlertRecordingConnection {
+    fn drop(&mut self) {
+        *self.alert.lock().unwrap() = self.connection.alert();
+    }
+}
+
+/// s2n-tls only exposes the alert that the peer sent, through
+/// `s2n_connection_get_alert`, which [AlertRecorder] reads when the handshake
+/// fails. The alerts that s2n-tls sends aren't exposed.
+fn alert(error: &(dyn Error + Send + Sync + 'static)) -> Option<TlsAlert> {
+    match crate::find_error::<InteropError>(error)? {
+        InteropError::Handshake { alert, .. } => *alert,
+        _ => None,
+    }
+}
+
+/// Write the blocks of the large data scenarios, sending a KeyUpdate with each
+/// block. s2n-tls counts the KeyUpdates, so this also checks that they were sent.
+async fn write_blocks_with_key_updates<T: AsyncRead + AsyncWrite + Unpin>(
+    stream: &mut s2n_tls_tokio::TlsStream<T, AlertRecordingConnection>,
+    volume: DataVolume,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    crate::write_blocks(stream, volume, |stream| {
+        stream
+            .as_mut()
+            .request_key_update(s2n_tls::enums::PeerKeyUpdate::KeyUpdateNotRequested)?;
+        Ok(())
+    })
+    .await?;
+
+    let updates = stream.as_ref().key_update_counts()?;
+    if updates.send_key_updates == 0 {
+        return Err(InteropError::KeyUpdateNotExchanged(KeyUpdateCounts {
+            sent: 0,
+            received: updates.recv_key_updates.into(),
+        })
+        .into());
+    }
+    Ok(())
+}
+
 impl std::fmt::Display for S2NShim {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "s2n-tls")
     }
 }
 
+/// Selects the security policy which only enables the requested protocol version
+/// and algorithms. s2n-tls can only be restricted to the algorithms of one of
+/// its numbered policies, so `None` is returned if no policy matches. No policy
+/// enables only TLS_AES_128_GCM_SHA256, TLS_CHACHA20_POLY1305_SHA256, x25519
+/// or secp256r1, so those pins are unimplemented.
+fn security_policy(
+    test: InteropTest,
+    parameters: TestParameters,
+) -> Result<Option<Policy>, s2n_tls::error::Error> {
+    let group = parameters.group_for(test);
+    let policy = match (parameters.tls_version, parameters.cipher_suite, group) {
+        (TlsVersion::Tls13, None, None) => DEFAULT_TLS13,
+        (TlsVersion::Tls12, None, None) => Policy::from_version(TLS12_POLICY)?,
+        (
+            TlsVersion::Tls13,
+            None | Some(CipherSuite::Aes256GcmSha384),
+            None | Some(KeyExchangeGroup::Secp384r1),
+        ) => Policy::from_version(P384_ONLY_POLICY)?,
+        (TlsVersion::Tls13, None, Some(KeyExchangeGroup::X25519Mlkem768)) => {
+            Policy::from_version(PQ_POLICY)?
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(policy))
+}
+
+/// s2n-tls authenticates the server with the server name that was sent in the
+/// SNI extension. Clients which don't send SNI must authenticate the server
+/// with this callback instead.
+struct VerifyServerName(&'static str);
+
+impl VerifyHostNameCallback for VerifyServerName {
+    fn verify_host_name(&self, host_name: &str) -> bool {
+        host_name == self.0
+    }
+}
+
+/// s2n-tls measures its blinding delay with this clock. It follows tokio's
+/// clock so that the delay also elapses when time is simulated, e.g. by turmoil.
+/// The real binaries keep s2n-tls' own clock.
+#[cfg(any(test, feature = "simulation"))]
+struct TokioClock(tokio::time::Instant);
+
+#[cfg(any(test, feature = "simulation"))]
+impl s2n_tls::callbacks::MonotonicClock for TokioClock {
+    fn get_time(&self) -> std::time::Duration {
+        self.0.elapsed()
+    }
+}
+
 #[derive(Default, Clone)]
 struct SessionTicketStorage {
     ticket:Arc<Mutex<Option<Vec<u8>>>>,
@@ -51,72 +253,153 @@ impl ConnectionInitializer for SessionTicketStorage {
 
 impl<T: AsyncRead + AsyncWrite + Unpin + Send> ClientTLS<T> for S2NShim {
     type Config = s2n_tls::config::Config;
-    type Connector = s2n_tls_tokio::TlsConnector;
-    type Stream = s2n_tls_tokio::TlsStream<T>;
+    type Connector = s2n_tls::config::Config;
+    type Stream = s2n_tls_tokio::TlsStream<T, AlertRecordingConnection>;
 
     fn get_client_config(
         test: common::InteropTest,
+        parameters: TestParameters,
     ) -> Result<Option<Self::Config>, Box<dyn Error>> {
         let ca_pem = std::fs::read(common::pem_file_path(common::PemType::CaCert))?;
+        let Some(policy) = security_policy(test, parameters)? else {
+            return Ok(None);
+        };
         let mut config = Config::builder();
-        config.set_security_policy(&DEFAULT_TLS13)?;
+        config.set_security_policy(&policy)?;
+        #[cfg(any(test, feature = "simulation"))]
+        config.set_monotonic_clock(TokioClock(tokio::time::Instant::now()))?;
         config.trust_pem(&ca_pem)?;
+        if !test.client_alpn_protocols().is_empty() {
+            config.set_application_protocol_preference(test.client_alpn_protocols())?;
+        }
+        if test.server_name().is_none() {
+            config.set_verify_host_callback(VerifyServerName(common::SERVER_NAME))?;
+        }
+
+        if let Some((chain, key)) = test.client_certificate() {
+            config.load_pem(
+                &std::fs::read(common::pem_file_path(chain))?,
+                &std::fs::read(common::pem_file_path(key))?,
+            )?;
+        }
 
         // additional configuration
         match test {
-            InteropTest::MTLSRequestResponse => {
-                config.load_pem(
-                    &std::fs::read(common::pem_file_path(common::PemType::ClientChain))?,
-                    &std::fs::read(common::pem_file_path(common::PemType::ClientKey))?,
-                )?;
-            },
             InteropTest::SessionResumption => {
                 let storage = SessionTicketStorage::default();
                 config.set_session_ticket_callback(storage.clone())?;
                 config.set_connection_initializer(storage.clone())?;
             }
+            // s2n-tls does not support requesting a key update from the peer
+            InteropTest::KeyUpdateRequest => return Ok(None),
+            // the key update flood is scripted with rustls, which can queue
+            // key updates on demand
+            InteropTest::KeyUpdateFlood => return Ok(None),
+            // the session ticket callback isn't invoked for session ids, so
+            // s2n-tls clients can only resume sessions with tickets
+            InteropTest::SessionIdResumption => return Ok(None),
+            // s2n-tls clients never initiate a renegotiation
+            InteropTest::RenegotiationRefusal => return Ok(None),
             _ => {/* no additional configuration required */},
         }
         Ok(Some(config.build()?))
     }
 
     fn connector(config: Self::Config) -> Self::Connector {
-        s2n_tls_tokio::TlsConnector::new(config)
+        config
     }
 
     async fn connect(
         client: &Self::Connector,
+        server_name: Option<&str>,
         transport_stream: T,
     ) -> Result<Self::Stream, Box<dyn Error + Send + Sync>> {
-        Ok(client.connect("localhost", transport_stream).await?)
+        let recorder = AlertRecorder::new(client);
+        // s2n-tls doesn't send the SNI extension if the server name is empty
+        s2n_tls_tokio::TlsConnector::new(recorder.clone())
+            .connect(server_name.unwrap_or(""), transport_stream)
+            .await
+            .map_err(|error| recorder.handshake_error(error))
+    }
+
+    async fn handle_large_data_upload_with_frequent_key_updates(
+        stream: &mut Self::Stream,
+        volume: DataVolume,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        write_blocks_with_key_updates(stream, volume).await
+    }
+
+    fn validate_hello_retry(stream: &Self::Stream) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        Ok(stream.as_ref()
+        .handshake_type()?
+        .contains("HELLO_RETRY_REQUEST"))
+    }
+
+    fn negotiated_params(stream: &Self::Stream) -> NegotiatedParams {
+        negotiated_params(stream.as_ref())
+    }
+
+    fn alert(error: &(dyn Error + Send + Sync + 'static)) -> Option<TlsAlert> {
+        alert(error)
     }
 }
 
 impl<T: AsyncRead + AsyncWrite + Unpin + Send> ServerTLS<T> for S2NShim {
     type Config = s2n_tls::config::Config;
-    type Acceptor = s2n_tls_tokio::TlsAcceptor;
-    type Stream = s2n_tls_tokio::TlsStream<T>;
+    type Acceptor = s2n_tls::config::Config;
+    type Stream = s2n_tls_tokio::TlsStream<T, AlertRecordingConnection>;
 
     fn get_server_config(
         test: InteropTest,
+        parameters: TestParameters,
     ) -> Result<Option<s2n_tls::config::Config>, Box<dyn Error>> {
         info!("getting the server config for {}", test);
-        let cert_pem = std::fs::read(common::pem_file_path(common::PemType::ServerChain))?;
-        let key_pem = std::fs::read(common::pem_file_path(common::PemType::ServerKey))?;
+        let (chain, key) = test.server_certificate();
+        let cert_pem = std::fs::read(common::pem_file_path(chain))?;
+        let key_pem = std::fs::read(common::pem_file_path(key))?;
+        let Some(policy) = security_policy(test, parameters)? else {
+            return Ok(None);
+        };
         let mut config = Config::builder();
-        config.set_security_policy(&DEFAULT_TLS13)?;
-        config.load_pem(&cert_pem, &key_pem)?;
+        config.set_security_policy(&policy)?;
+        #[cfg(any(test, feature = "simulation"))]
+        config.set_monotonic_clock(TokioClock(tokio::time::Instant::now()))?;
+        if test.uses_alternate_certificate() {
+            // `load_pem` only supports a single certificate, so multiple chains
+            // are loaded into the store. The first chain is the default, and the
+            // alternate chain is only selected if its name is requested through SNI.
+            let mut alternate = cert_chain::Builder::new()?;
+            alternate.load_pem(
+                &std::fs::read(common::pem_file_path(common::PemType::AlternateServerChain))?,
+                &std::fs::read(common::pem_file_path(common::PemType::AlternateServerKey))?,
+            )?;
+            let mut default = cert_chain::Builder::new()?;
+            default.load_pem(&cert_pem, &key_pem)?;
+            config.load_chain(default.build()?)?;
+            config.load_chain(alternate.build()?)?;
+        } else {
+            config.load_pem(&cert_pem, &key_pem)?;
+        }
+        if !test.server_alpn_protocols().is_empty() {
+            config.set_application_protocol_preference(test.server_alpn_protocols())?;
+        }
+        if test.requires_client_auth() {
+            config.set_client_auth_type(ClientAuthType::Required)?;
+            config.trust_pem(&std::fs::read(common::pem_file_path(
+                common::PemType::CaCert,
+            ))?)?;
+        }
         match test{
-            InteropTest::MTLSRequestResponse => {
-                config.trust_pem(&std::fs::read(common::pem_file_path(
-                    common::PemType::CaCert,
-                ))?)?;
-            },
             InteropTest::SessionResumption => {
                 config
                     .enable_session_tickets(true)?
                     .add_session_ticket_key(STEK_NAME, &STEK_VALUE, SystemTime::UNIX_EPOCH)?;
             }
+            InteropTest::HelloRetryRequest => {
+                config.set_security_policy(&Policy::from_version(P384_ONLY_POLICY)?)?;
+            }
+            // s2n-tls servers don't have a session id cache
+            InteropTest::SessionIdResumption => return Ok(None),
             _ => {/* no additional configuration required */}
 
         }
@@ -124,52 +407,52 @@ impl<T: AsyncRead + AsyncWrite + Unpin + Send> ServerTLS<T> for S2NShim {
     }
 
     fn acceptor(config: Self::Config) -> Self::Acceptor {
-        s2n_tls_tokio::TlsAcceptor::new(config)
+        config
     }
 
     async fn accept(
         server: &Self::Acceptor,
         transport_stream: T,
     ) -> Result<Self::Stream, Box<dyn Error + Send + Sync>> {
-        Ok(server.accept(transport_stream).await?)
+        let recorder = AlertRecorder::new(server);
+        s2n_tls_tokio::TlsAcceptor::new(recorder.clone())
+            .accept(transport_stream)
+            .await
+            .map_err(|error| recorder.handshake_error(error))
     }
 
     async fn handle_large_data_download_with_frequent_key_updates(
         stream: &mut Self::Stream,
+        volume: DataVolume,
     ) -> Result<(), Box<dyn Error + Send + Sync>> {
-        tracing::info!("waiting for client greeting");
-        let mut server_greeting_buffer = vec![0; CLIENT_GREETING.as_bytes().len()];
-        stream.read_exact(&mut server_greeting_buffer).await?;
-        assert_eq!(server_greeting_buffer, CLIENT_GREETING.as_bytes());
-
-        let mut data_buffer = vec![0; 1_000_000];
-        for i in 0..LARGE_DATA_DOWNLOAD_GB {
-            stream
-                .as_mut()
-                .request_key_update(s2n_tls::enums::PeerKeyUpdate::KeyUpdateNotRequested)?;
-            if i % 10 == 0 {
-                tracing::info!(
-                    "GB sent: {}, key updates: {:?}",
-                    i,
-                    stream.as_ref().key_update_counts()?
-                );
-            }
-            data_buffer[0] = (i % u8::MAX as u64) as u8;
-            for j in 0..1_000 {
-                tracing::trace!("{}-{}", i, j);
-                stream.write_all(&data_buffer).await?;
-            }
-        }
+        write_blocks_with_key_updates(stream, volume).await
+    }
 
-        let updates = stream.as_ref().key_update_counts()?;
-        assert!(updates.send_key_updates > 0);
-        Ok(())
+    fn validate_resumption(stream: &Self::Stream) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        Ok(!stream.as_ref()
+        .handshake_type()?
+        .contains("FULL_HANDSHAKE"))
+    }
+
+    fn validate_hello_retry(stream: &Self::Stream) -> Result<bool, Box<dyn Error + Send + Sync>> {
+        Ok(stream.as_ref()
+        .handshake_type()?
+        .contains("HELLO_RETRY_REQUEST"))
+    }
+
+    fn negotiated_params(stream: &Self::Stream) -> NegotiatedParams {
+        negotiated_params(stream.as_ref())
+    }
+
+    fn alert(error: &(dyn Error + Send + Sync + 'static)) -> Option<TlsAlert> {
+        alert(error)
     }
 
-    fn validate_resumption(stream: &Self::Stream) -> bool {
-        !stream.as_ref()
-        .handshake_type()
-        .unwrap()
-        .contains("FULL_HANDSHAKE")
+    fn key_update_counts(stream: &Self::Stream) -> Option<KeyUpdateCounts> {
+        let counts = stream.as_ref().key_update_counts().ok()?;
+        Some(KeyUpdateCounts {
+            sent: counts.send_key_updates.into(),
+            received: counts.recv_key_updates.into(),
+        })
     }
 }
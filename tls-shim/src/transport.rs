@@ -0,0 +1,332 @@
+// Copyright Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Transport adapters that can be used as the `T` in [`ServerTLS<T>`](crate::ServerTLS)
+//! and [`ClientTLS<T>`](crate::ClientTLS) to alter how bytes are delivered
+//! to the TLS implementations.
+
+use std::{
+    io::{self, IoSlice},
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll, Waker},
+};
+
+use common::{InteropTest, SMALL_TCP_PACKET_BYTES};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// `FragmentingStream` limits every read and write on the inner transport to
+/// at most `max_fragment_len` bytes. This forces TLS implementations to
+/// reassemble records (and handshake messages) from many small pieces.
+#[derive(Debug)]
+pub struct FragmentingStream<T> {
+    inner: T,
+    max_fragment_len: usize,
+}
+
+impl<T> FragmentingStream<T> {
+    pub fn new(inner: T, max_fragment_len: usize) -> Self {
+        assert!(max_fragment_len > 0);
+        FragmentingStream {
+            inner,
+            max_fragment_len,
+        }
+    }
+
+    /// Wrap `inner` with the fragmentation required by `test`. Only the
+    /// `SmallTcpPacket` scenario fragments the transport, all other scenarios
+    /// pass reads and writes straight through.
+    pub fn for_test(inner: T, test: InteropTest) -> Self {
+        match test {
+            InteropTest::SmallTcpPacket => Self::new(inner, SMALL_TCP_PACKET_BYTES),
+            _ => Self::new(inner, usize::MAX),
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for FragmentingStream<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() <= this.max_fragment_len {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+
+        let mut fragment = buf.take(this.max_fragment_len);
+        ready!(Pin::new(&mut this.inner).poll_read(cx, &mut fragment))?;
+        let read = fragment.filled().len();
+        // SAFETY: `fragment` is a view over the unfilled portion of `buf`, and
+        // the inner transport initialized the first `read` bytes of it.
+        unsafe {
+            buf.assume_init(read);
+        }
+        buf.advance(read);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for FragmentingStream<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let len = buf.len().min(this.max_fragment_len);
+        Pin::new(&mut this.inner).poll_write(cx, &buf[..len])
+    }
+
+    /// Vectored writes are passed through when they fit in a single fragment,
+    /// so that the inner transport can coalesce them.
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let total_len = bufs.iter().map(|buf| buf.len()).sum::<usize>();
+        if total_len <= this.max_fragment_len {
+            return Pin::new(&mut this.inner).poll_write_vectored(cx, bufs);
+        }
+        let buf = bufs
+            .iter()
+            .find(|buf| !buf.is_empty())
+            .map_or(&[][..], |buf| &**buf);
+        let len = buf.len().min(this.max_fragment_len);
+        Pin::new(&mut this.inner).poll_write(cx, &buf[..len])
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// The receive windows for both directions of a single connection.
+///
+/// Transports like turmoil or `tokio::io::duplex` buffer an unbounded amount of
+/// data, so a sender never notices that its peer has stopped reading. Wrapping
+/// both ends of a connection with the same `FlowControl` models a TCP receive
+/// window, so that flow control deadlocks can be reproduced in-process. Both
+/// ends must be in the same process, but the transport can be anything,
+/// including real sockets.
+#[derive(Clone, Debug)]
+pub struct FlowControl {
+    client_to_server: Window,
+    server_to_client: Window,
+}
+
+impl FlowControl {
+    pub fn new(window_len: usize) -> Self {
+        assert!(window_len > 0);
+        FlowControl {
+            client_to_server: Window::new(window_len),
+            server_to_client: Window::new(window_len),
+        }
+    }
+
+    pub fn client<T>(&self, inner: T) -> FlowControlledStream<T> {
+        FlowControlledStream {
+            inner,
+            send: self.client_to_server.clone(),
+            receive: self.server_to_client.clone(),
+        }
+    }
+
+    pub fn server<T>(&self, inner: T) -> FlowControlledStream<T> {
+        FlowControlledStream {
+            inner,
+            send: self.server_to_client.clone(),
+            receive: self.client_to_server.clone(),
+        }
+    }
+
+    /// Whether either direction has data that was written but not read yet,
+    /// e.g. because the transport lost it.
+    pub fn has_unread(&self) -> bool {
+        self.client_to_server.unread() > 0 || self.server_to_client.unread() > 0
+    }
+}
+
+/// The bytes in one direction of a connection that were written, but haven't
+/// been read by the peer yet.
+#[derive(Clone, Debug)]
+struct Window(Arc<Mutex<WindowState>>);
+
+#[derive(Debug)]
+struct WindowState {
+    len: usize,
+    unread: usize,
+    blocked_writer: Option<Waker>,
+}
+
+impl Window {
+    fn new(len: usize) -> Self {
+        Window(Arc::new(Mutex::new(WindowState {
+            len,
+            unread: 0,
+            blocked_writer: None,
+        })))
+    }
+
+    /// Returns the number of bytes that can be written, once there are any.
+    fn poll_available(&self, cx: &mut Context<'_>) -> Poll<usize> {
+        let mut state = self.0.lock().unwrap();
+        let available = state.len - state.unread;
+        if available == 0 {
+            state.blocked_writer = Some(cx.waker().clone());
+            return Poll::Pending;
+        }
+        Poll::Ready(available)
+    }
+
+    fn unread(&self) -> usize {
+        self.0.lock().unwrap().unread
+    }
+
+    fn written(&self, len: usize) {
+        self.0.lock().unwrap().unread += len;
+    }
+
+    fn read(&self, len: usize) {
+        let mut state = self.0.lock().unwrap();
+        state.unread = state.unread.saturating_sub(len);
+        if len > 0 {
+            if let Some(writer) = state.blocked_writer.take() {
+                writer.wake();
+            }
+        }
+    }
+}
+
+/// One end of a connection with [`FlowControl`]. Writes are limited to the
+/// space left in the peer's receive window, and block while it is full.
+#[derive(Debug)]
+pub struct FlowControlledStream<T> {
+    inner: T,
+    send: Window,
+    receive: Window,
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for FlowControlledStream<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let filled = buf.filled().len();
+        ready!(Pin::new(&mut this.inner).poll_read(cx, buf))?;
+        this.receive.read(buf.filled().len() - filled);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for FlowControlledStream<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if buf.is_empty() {
+            return Pin::new(&mut this.inner).poll_write(cx, buf);
+        }
+        let available = ready!(this.send.poll_available(cx));
+        let len = buf.len().min(available);
+        let written = ready!(Pin::new(&mut this.inner).poll_write(cx, &buf[..len]))?;
+        this.send.written(written);
+        Poll::Ready(Ok(written))
+    }
+
+    /// Vectored writes are passed through when they fit in the window.
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let total_len = bufs.iter().map(|buf| buf.len()).sum::<usize>();
+        if total_len == 0 {
+            return Pin::new(&mut this.inner).poll_write_vectored(cx, bufs);
+        }
+        let available = ready!(this.send.poll_available(cx));
+        let written = if total_len <= available {
+            ready!(Pin::new(&mut this.inner).poll_write_vectored(cx, bufs))?
+        } else {
+            let buf = bufs
+                .iter()
+                .find(|buf| !buf.is_empty())
+                .map_or(&[][..], |buf| &**buf);
+            let len = buf.len().min(available);
+            ready!(Pin::new(&mut this.inner).poll_write(cx, &buf[..len]))?
+        };
+        this.send.written(written);
+        Poll::Ready(Ok(written))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[tokio::test]
+    async fn fragments_reads_and_writes() {
+        let (client, server) = tokio::io::duplex(1024);
+        let mut client = FragmentingStream::new(client, 1);
+        let mut server = FragmentingStream::new(server, 3);
+
+        assert_eq!(client.write(b"hello").await.unwrap(), 1);
+        client.write_all(b"ello").await.unwrap();
+
+        let mut buffer = [0; 5];
+        assert_eq!(server.read(&mut buffer).await.unwrap(), 3);
+        server.read_exact(&mut buffer[3..]).await.unwrap();
+        assert_eq!(&buffer, b"hello");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn flow_control_blocks_writes_until_the_peer_reads() {
+        let (client, server) = tokio::io::duplex(1024);
+        let flow_control = FlowControl::new(4);
+        let mut client = flow_control.client(client);
+        let mut server = flow_control.server(server);
+
+        assert_eq!(client.write(b"hello").await.unwrap(), 4);
+        let blocked = tokio::time::timeout(Duration::from_secs(1), client.write(b"o")).await;
+        assert!(blocked.is_err());
+
+        let mut buffer = [0; 5];
+        assert_eq!(server.read(&mut buffer[..2]).await.unwrap(), 2);
+        assert_eq!(client.write(b"o").await.unwrap(), 1);
+        server.read_exact(&mut buffer[2..]).await.unwrap();
+        assert_eq!(&buffer, b"hello");
+
+        // the windows of the two directions are independent
+        server.write_all(b"ok").await.unwrap();
+    }
+}
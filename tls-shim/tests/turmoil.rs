@@ -1,136 +1,710 @@
-use common::InteropTest;
+use common::{DataVolume, ExitCode, InteropTest, TestParameters, TlsVersion};
 use rand::SeedableRng;
 use tracing::Level;
 
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::{
+    error::Error,
+    fmt::Display,
+    io::{self, IoSlice},
+    net::{Ipv4Addr, SocketAddrV4},
+    ops::Range,
+    panic::AssertUnwindSafe,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
 use tls_shim_interop::{
-    openssl_shim::OpensslShim, rustls_shim::RustlsShim, s2n_tls_shim::S2NShim, ClientTLS, ServerTLS,
+    driver,
+    openssl_shim::OpensslShim,
+    rustls_shim::RustlsShim,
+    s2n_tls_shim::S2NShim,
+    transport::{FlowControl, FlowControlledStream, FragmentingStream},
+    ClientTLS, ConnectionFailure, InteropError, ServerTLS,
 };
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, ReadBuf};
 
-use turmoil::Sim;
-
-// turmoil's send function seems to be quadratic somewhere. Sending 1 Gb takes approximately 229 seconds
-// so don't enable the large data tests.
-const TEST_CASES: [InteropTest; 1] = [
-    //InteropTest::Greeting,
-    //InteropTest::Handshake,
-    //InteropTest::MTLSRequestResponse,
-    InteropTest::SessionResumption,
-    // InteropTest::LargeDataDownload,
-    // InteropTest::LargeDataDownloadWithFrequentKeyUpdates,
-];
+type Transport = FragmentingStream<FlowControlledStream<CoalescingStream<turmoil::net::TcpStream>>>;
+type ServerTransport = FaultInjectingStream<Transport>;
 
 const PORT: u16 = 1738;
+const DEFAULT_SEEDS: Range<u64> = 0..4;
+// large enough for a key update in the frequent key update scenarios, and
+// small enough that the key update flood finishes quickly
+const DEFAULT_DATA_VOLUME: DataVolume = DataVolume {
+    blocks: 2,
+    mb_per_block: 1,
+};
+
+// turmoil panics instead of applying backpressure when a host's buffer is
+// full, and `FragmentingStream` splits records into many small segments
+const TCP_CAPACITY: usize = 1 << 16;
+
+// the negative scenarios wait out s2n-tls's blinding delay, which can be up
+// to 30 seconds of simulated time
+const SIMULATION_DURATION: Duration = Duration::from_secs(120);
+// peers that wait out a deadline or a blinding delay spend most of the
+// simulation idle, so coarser ticks keep those tests fast
+const TICK_DURATION: Duration = Duration::from_millis(10);
+
+/// Each connection must either complete or fail within this much simulated
+/// time. None of the TLS implementations time out on their own, so a peer
+/// that is stuck waiting for a lost message fails at the deadline.
+const DEADLINE: Duration = Duration::from_secs(60);
+
+/// How long a partition or hold lasts before the link is healed.
+const FAULT_DURATION: Duration = Duration::from_secs(1);
+
+/// The receive window of each direction under `Fault::ReceiveWindow`, a
+/// typical default for Linux TCP sockets.
+const RECEIVE_WINDOW: usize = 64 * 1024;
+
+/// Network faults that can be injected into a scenario.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Fault {
+    /// every message takes 100ms to cross the link
+    Latency,
+    /// messages take anywhere from 0 to 500ms to cross the link
+    Jitter,
+    /// the link randomly breaks and is later repaired, dropping any messages
+    /// that are sent while it is broken
+    MessageLoss,
+    /// the link is partitioned just before the server sends its first flight,
+    /// and repaired after `FAULT_DURATION`
+    Partition,
+    /// messages are held just before the server sends its first flight, and
+    /// released after `FAULT_DURATION`
+    HoldRelease,
+    /// each direction of the link only buffers `RECEIVE_WINDOW` bytes that the
+    /// peer hasn't read yet, and writes block until the peer reads
+    ReceiveWindow,
+}
+
+impl Fault {
+    fn configure(self, builder: &mut turmoil::Builder) {
+        match self {
+            Fault::Latency => {
+                builder
+                    .min_message_latency(Duration::from_millis(100))
+                    .max_message_latency(Duration::from_millis(100));
+            }
+            Fault::Jitter => {
+                builder
+                    .min_message_latency(Duration::ZERO)
+                    .max_message_latency(Duration::from_millis(500));
+            }
+            Fault::MessageLoss => {
+                builder.fail_rate(0.05).repair_rate(0.1);
+            }
+            Fault::Partition | Fault::HoldRelease | Fault::ReceiveWindow => {}
+        }
+    }
+
+    /// Called on the server just before it sends its first flight, which is
+    /// in the middle of the handshake.
+    fn inject(self) {
+        match self {
+            Fault::Partition => {
+                turmoil::partition("client", "server");
+                tokio::spawn(async {
+                    tokio::time::sleep(FAULT_DURATION).await;
+                    turmoil::repair("client", "server");
+                });
+            }
+            Fault::HoldRelease => {
+                turmoil::hold("client", "server");
+                tokio::spawn(async {
+                    tokio::time::sleep(FAULT_DURATION).await;
+                    turmoil::release("client", "server");
+                });
+            }
+            Fault::Latency | Fault::Jitter | Fault::MessageLoss | Fault::ReceiveWindow => {}
+        }
+    }
+
+    /// turmoil buffers an unbounded amount of data, so only
+    /// `Fault::ReceiveWindow` limits how much can be in flight.
+    fn flow_control(fault: Option<Self>) -> FlowControl {
+        match fault {
+            Some(Fault::ReceiveWindow) => FlowControl::new(RECEIVE_WINDOW),
+            _ => FlowControl::new(usize::MAX),
+        }
+    }
+
+    /// turmoil doesn't retransmit dropped TCP segments, so the peers can't
+    /// recover from faults that drop messages. They must still fail cleanly
+    /// before the deadline.
+    fn recoverable(self) -> bool {
+        !matches!(self, Fault::MessageLoss | Fault::Partition)
+    }
+}
+
+/// `FaultInjectingStream` injects `fault` the first time that the server
+/// writes to the connection.
+#[derive(Debug)]
+struct FaultInjectingStream<T> {
+    inner: T,
+    fault: Option<Fault>,
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for FaultInjectingStream<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for FaultInjectingStream<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if let Some(fault) = this.fault.take() {
+            tracing::info!("injecting {fault:?}");
+            fault.inject();
+        }
+        Pin::new(&mut this.inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// `CoalescingStream` sends each vectored write as a single segment. turmoil
+/// streams don't support vectored writes, so the TLS implementations would
+/// otherwise send every record that they have buffered as its own segment,
+/// and the simulation slows to a crawl when thousands of key updates are
+/// flushed at once.
+#[derive(Debug)]
+struct CoalescingStream<T> {
+    inner: T,
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for CoalescingStream<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for CoalescingStream<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let segment: Vec<u8> = bufs.iter().flat_map(|buf| buf.iter().copied()).collect();
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, &segment)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        true
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// The large data scenarios transfer `TURMOIL_DATA_VOLUME`, e.g.
+/// `TURMOIL_DATA_VOLUME=quick`, or a scaled down `DEFAULT_DATA_VOLUME`.
+fn data_volume() -> DataVolume {
+    match std::env::var("TURMOIL_DATA_VOLUME") {
+        Ok(volume) => volume.parse().expect("invalid TURMOIL_DATA_VOLUME"),
+        Err(_) => DEFAULT_DATA_VOLUME,
+    }
+}
+
+/// Scenarios run with the latest TLS version that they support.
+fn parameters(test: InteropTest) -> TestParameters {
+    let tls_version = if test.supports_tls_version(TlsVersion::Tls13) {
+        TlsVersion::Tls13
+    } else {
+        TlsVersion::Tls12
+    };
+    TestParameters {
+        data_volume: data_volume(),
+        tls_version,
+        cipher_suite: None,
+        group: None,
+    }
+}
+
+/// A connection didn't complete or fail within [DEADLINE].
+#[derive(Debug)]
+struct DeadlineExceeded;
+
+impl Display for DeadlineExceeded {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the deadline was exceeded")
+    }
+}
+
+impl Error for DeadlineExceeded {}
+
+/// Under a fault that the peers can't recover from, a connection only needs
+/// to fail cleanly, with a handshake or an IO error. It may only hang until
+/// the deadline if the network lost data that a peer is waiting for, since
+/// turmoil doesn't retransmit and none of the TLS implementations time out.
+/// A peer that hangs with nothing left to read, or a connection that fails one
+/// of the checks of the scenario, is still a failure.
+fn tolerate(
+    fault: Option<Fault>,
+    flow_control: &FlowControl,
+    result: turmoil::Result,
+) -> turmoil::Result {
+    let Err(e) = result else {
+        return Ok(());
+    };
+    let tolerated = if let Some(failure) = e.downcast_ref::<ConnectionFailure>() {
+        matches!(
+            failure.exit_code,
+            ExitCode::HandshakeFailure | ExitCode::IoError
+        )
+    } else if e.is::<DeadlineExceeded>() {
+        flow_control.has_unread()
+    } else {
+        // e.g. the TCP connection couldn't be established
+        e.downcast_ref::<io::Error>()
+            .is_some_and(|e| e.kind() != io::ErrorKind::TimedOut)
+    };
+    match fault {
+        Some(fault) if !fault.recoverable() && tolerated => {
+            tracing::info!("connection failed under {fault:?}: {e}");
+            Ok(())
+        }
+        _ => Err(e),
+    }
+}
 
-// async fn server_handle_connection<T>(test: InteropTest, acceptor: T::Config) -> Result<(), Box<dyn std::error::Error>> 
-// where
-//     T: ServerTLS<turmoil::net::TcpStream>
-// {
-//     let server = T::acceptor(config);
+async fn accept_connection<S>(
+    acceptor: &S::Acceptor,
+    listener: &turmoil::net::TcpListener,
+    flow_control: &FlowControl,
+    test: InteropTest,
+    fault: Option<Fault>,
+) -> turmoil::Result
+where
+    S: ServerTLS<ServerTransport>,
+{
+    let (stream, _peer_addr) = listener.accept().await?;
+    let stream = FaultInjectingStream {
+        inner: FragmentingStream::for_test(
+            flow_control.server(CoalescingStream { inner: stream }),
+            test,
+        ),
+        fault,
+    };
 
-//     let listener =
-//         turmoil::net::TcpListener::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT)).await?;
+    let accepted = driver::accept::<S, _>(acceptor, stream, test, parameters(test));
+    tokio::time::timeout(DEADLINE, accepted)
+        .await
+        .unwrap_or_else(|_| Err(DeadlineExceeded.into()))
+        .map_err(|e| e as Box<dyn Error>)
+}
 
-//     let (stream, _peer_addr) = listener.accept().await?;
+async fn server_loop<S>(
+    test: InteropTest,
+    acceptor: S::Acceptor,
+    flow_control: FlowControl,
+    fault: Option<Fault>,
+) -> turmoil::Result
+where
+    S: ServerTLS<ServerTransport>,
+{
+    let listener =
+        turmoil::net::TcpListener::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT)).await?;
 
-//     let server_clone = server.clone();
-//     let tls = T::accept(&server_clone, stream).await.unwrap();
-//     T::handle_server_connection(test, tls).await.unwrap();
-//     Ok(())
-// }
+    for test in driver::server_scenarios(test) {
+        let result = accept_connection::<S>(&acceptor, &listener, &flow_control, test, fault).await;
+        tolerate(fault, &flow_control, result)?;
+    }
+    Ok(())
+}
 
-async fn server_loop<T>(test: InteropTest) -> Result<(), Box<dyn std::error::Error>>
+async fn connect<C>(
+    connector: &C::Connector,
+    flow_control: &FlowControl,
+    test: InteropTest,
+) -> turmoil::Result
 where
-    T: ServerTLS<turmoil::net::TcpStream>,
+    C: ClientTLS<Transport>,
 {
-    let config = T::get_server_config(test)?.unwrap();
-    let listener = turmoil::net::TcpListener::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT)).await?;
+    tokio::time::timeout(DEADLINE, async {
+        let stream = turmoil::net::TcpStream::connect(("server", PORT)).await?;
+        let stream = FragmentingStream::for_test(
+            flow_control.client(CoalescingStream { inner: stream }),
+            test,
+        );
+        driver::connect::<C, _>(connector, stream, test, parameters(test)).await
+    })
+    .await
+    .unwrap_or_else(|_| Err(DeadlineExceeded.into()))
+    .map_err(|e| e as Box<dyn Error>)
+}
 
-    let server = T::acceptor(config);
-    
-    if test == InteropTest::SessionResumption {
-        let (stream, _peer_addr) = listener.accept().await?;
-        let server_clone = server.clone();
-        let tls = T::accept(&server_clone, stream).await.unwrap();
-        T::handle_server_connection(InteropTest::Greeting, tls).await.unwrap();
+async fn client_loop<C>(
+    test: InteropTest,
+    connector: C::Connector,
+    flow_control: FlowControl,
+    fault: Option<Fault>,
+) -> turmoil::Result
+where
+    C: ClientTLS<Transport>,
+{
+    for test in driver::client_scenarios(test) {
+        let result = connect::<C>(&connector, &flow_control, test).await;
+        tolerate(fault, &flow_control, result)?;
     }
-    let (stream, _peer_addr) = listener.accept().await?;
-    let server_clone = server.clone();
-    let tls = T::accept(&server_clone, stream).await.unwrap();
-    T::handle_server_connection(test, tls).await.unwrap();
     Ok(())
 }
 
-async fn client_loop<T>(
+/// The seeds that each scenario is run with. `TURMOIL_SEEDS` overrides the
+/// default range, e.g. `TURMOIL_SEEDS=0..1000`, and `TURMOIL_SEED` reproduces
+/// a single seed with trace logging.
+fn seeds() -> Range<u64> {
+    if let Some(seed) = reproduced_seed() {
+        return seed..seed + 1;
+    }
+    let Ok(seeds) = std::env::var("TURMOIL_SEEDS") else {
+        return DEFAULT_SEEDS;
+    };
+    let (start, end) = seeds
+        .split_once("..")
+        .expect("TURMOIL_SEEDS must be a range, e.g. 0..100");
+    let start = start.parse().expect("invalid start of TURMOIL_SEEDS");
+    let end = end.parse().expect("invalid end of TURMOIL_SEEDS");
+    start..end
+}
+
+fn reproduced_seed() -> Option<u64> {
+    let seed = std::env::var("TURMOIL_SEED").ok()?;
+    Some(seed.parse().expect("TURMOIL_SEED must be an integer"))
+}
+
+/// Whether a connection failed because it didn't make progress, either at the
+/// deadline or at a timeout of the shims.
+fn timed_out(e: &(dyn Error + 'static)) -> bool {
+    match e.downcast_ref::<ConnectionFailure>() {
+        Some(failure) => failure.exit_code == ExitCode::Timeout,
+        None => e.is::<DeadlineExceeded>(),
+    }
+}
+
+/// Run `test` between an `S` server and a `C` client with every seed, and
+/// report the first seed that fails. Scenarios in `known_failures` must fail
+/// with every seed, so that a fix is noticed. Scenarios in `flaky` only fail
+/// with certain seeds or data volumes, so they are allowed to either pass or
+/// fail. Scenarios in `deadlocks` must fail by timing out.
+fn run_scenario<S, C>(
     test: InteropTest,
-    server_domain: String,
-) -> Result<(), Box<dyn std::error::Error>>
+    fault: Option<Fault>,
+    known_failures: &[InteropTest],
+    flaky: &[InteropTest],
+    deadlocks: &[InteropTest],
+) -> turmoil::Result
 where
-    T: ClientTLS<turmoil::net::TcpStream>,
+    S: ServerTLS<ServerTransport> + 'static,
+    C: ClientTLS<Transport> + 'static,
 {
-    let config = T::get_client_config(test)?.unwrap();
-    let client = T::connector(config);
-
-    if test == InteropTest::SessionResumption {
-        let transport_stream = turmoil::net::TcpStream::connect((server_domain.as_str(), PORT)).await?;
-        let tls = T::connect(&client, transport_stream).await.unwrap();
-        // I keep getting panics here
-        // called `Result::unwrap()` on an `Err` value: Custom { kind: ConnectionReset, error: "Connection reset" }
-        // note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
-        T::handle_client_connection(test, tls).await.unwrap();
-    }
-    let transport_stream = turmoil::net::TcpStream::connect((server_domain, PORT)).await?;
-    let tls = T::connect(&client, transport_stream).await.unwrap();
-    T::handle_client_connection(test, tls).await.unwrap();
+    let level = match reproduced_seed() {
+        Some(_) => Level::TRACE,
+        None => Level::INFO,
+    };
+    let _ = tracing_subscriber::fmt::fmt()
+        .with_max_level(level)
+        .with_test_writer()
+        .try_init();
+
+    for seed in seeds() {
+        tracing::info!("running {test} with seed {seed}");
+        // turmoil panics on some errors, e.g. if a host's buffer overflows
+        let result =
+            std::panic::catch_unwind(AssertUnwindSafe(|| run_seed::<S, C>(test, fault, seed)))
+                .unwrap_or_else(|panic| {
+                    tracing::error!(
+                        "{test} panicked with seed {seed}, reproduce it with TURMOIL_SEED={seed}"
+                    );
+                    std::panic::resume_unwind(panic)
+                });
+        match result {
+            Ok(()) if known_failures.contains(&test) || deadlocks.contains(&test) => {
+                return Err(format!(
+                    "{test} is a known failure for this pairing, but it passed with seed {seed}"
+                )
+                .into())
+            }
+            Ok(()) => {}
+            Err(e) if deadlocks.contains(&test) => {
+                if !timed_out(e.as_ref()) {
+                    return Err(format!(
+                        "{test} is expected to deadlock, but it failed with seed {seed}: {e}"
+                    )
+                    .into());
+                }
+                tracing::info!("{test} deadlocked as expected with seed {seed}: {e}");
+            }
+            Err(e) if known_failures.contains(&test) => {
+                tracing::info!("{test} is a known failure, and failed with seed {seed}: {e}");
+            }
+            Err(e) if flaky.contains(&test) => {
+                tracing::info!("{test} is flaky, and failed with seed {seed}: {e}");
+            }
+            Err(e) => {
+                return Err(format!(
+                    "{test} failed with seed {seed}, reproduce it with TURMOIL_SEED={seed}: {e}"
+                )
+                .into())
+            }
+        }
+    }
     Ok(())
 }
 
-fn setup_scenario<S, C>(sim: &mut Sim, test: InteropTest)
+/// Run `test` between an `S` server and a `C` client in a simulated network,
+/// with `fault` injected into the network. Scenarios that either
+/// implementation doesn't support fail, so that they can't silently pass.
+fn run_seed<S, C>(test: InteropTest, fault: Option<Fault>, seed: u64) -> turmoil::Result
 where
-    S: ServerTLS<turmoil::net::TcpStream> + 'static,
-    C: ClientTLS<turmoil::net::TcpStream> + 'static,
+    S: ServerTLS<ServerTransport> + 'static,
+    C: ClientTLS<Transport> + 'static,
 {
-    // let server_name = format!(
-    //     "{}-{}-{}-server",
-    //     std::any::type_name::<S>(),
-    //     std::any::type_name::<C>(),
-    //     test
-    // );
-    // let client_name = format!(
-    //     "{}-{}-{}-client",
-    //     std::any::type_name::<S>(),
-    //     std::any::type_name::<C>(),
-    //     test
-    // );
-    let server_name = format!(
-        "{}-server",
-        test
-    );
-    let client_name = format!(
-        "{}-client",
-        test
+    // the configs are rebuilt for every seed, so that session tickets from one
+    // run can't be resumed in the next
+    let server_config = S::get_server_config(test, parameters(test))?;
+    let client_config = C::get_client_config(test, parameters(test))?;
+    let (Some(server_config), Some(client_config)) = (server_config, client_config) else {
+        return Err(InteropError::UnsupportedScenario(test).into());
+    };
+    let acceptor = S::acceptor(server_config);
+    let connector = C::connector(client_config);
+
+    let mut builder = turmoil::Builder::new();
+    builder
+        .simulation_duration(SIMULATION_DURATION)
+        .tick_duration(TICK_DURATION)
+        .tcp_capacity(TCP_CAPACITY);
+    if let Some(fault) = fault {
+        fault.configure(&mut builder);
+    }
+    let rand = Box::new(rand::rngs::SmallRng::seed_from_u64(seed));
+    let mut sim = builder.build_with_rng(rand);
+    // the connections run one after another, so they can share the windows
+    let flow_control = Fault::flow_control(fault);
+    let server_flow_control = flow_control.clone();
+    sim.host("server", move || {
+        server_loop::<S>(test, acceptor.clone(), server_flow_control.clone(), fault)
+    });
+    sim.client(
+        "client",
+        client_loop::<C>(test, connector, flow_control, fault),
     );
-    sim.host(server_name.as_str(), move || server_loop::<S>(test));
-    sim.client(client_name, client_loop::<C>(test, server_name));
+    sim.run()
+}
+
+/// Generate a module of tests for each server and client pairing, with one
+/// test per scenario, e.g. `s2n_tls_server_rustls_client::greeting`. A subset
+/// of the scenarios is also run under each network fault, e.g.
+/// `s2n_tls_server_rustls_client::hold_release::greeting`. Scenarios in
+/// `known_failures` must fail, and scenarios in `flaky` may fail. Scenarios in
+/// `deadlocks` must time out with a bounded receive window, and scenarios in
+/// `unsupported` are ignored.
+macro_rules! interop_tests {
+    ($($pairing:ident: $server:ty, $client:ty,
+        known_failures: [$($failure:ident),*],
+        flaky: [$($flaky:ident),*],
+        deadlocks: [$($deadlock:ident),*],
+        unsupported: [$($unsupported:ident),*];)*) => {
+        interop_tests!(@pairings ($) $($pairing: $server, $client,
+            [$($failure),*], [$($flaky),*], [$($deadlock),*], [$($unsupported),*];)*);
+    };
+    // `$d` is a literal `$`, so that the pairing's `scenario` macro can have
+    // its own metavariables
+    (@pairings ($d:tt) $($pairing:ident: $server:ty, $client:ty,
+        [$($failure:ident),*], [$($flaky:ident),*], [$($deadlock:ident),*],
+        [$($unsupported:ident),*];)*) => {
+        $(
+            mod $pairing {
+                use super::*;
+
+                macro_rules! scenario {
+                    $(($unsupported, $d($d test:tt)*) => {
+                        #[ignore = "not supported by this pairing"]
+                        $d($d test)*
+                    };)*
+                    ($d other:ident, $d($d test:tt)*) => { $d($d test)* };
+                }
+
+                const KNOWN_FAILURES: &[InteropTest] = &[$(InteropTest::$failure),*];
+                const FLAKY: &[InteropTest] = &[$(InteropTest::$flaky),*];
+                const DEADLOCKS: &[InteropTest] = &[];
+
+                interop_tests!(@scenarios $server, $client, None,
+                    handshake: Handshake,
+                    greeting: Greeting,
+                    large_data_download: LargeDataDownload,
+                    large_data_download_with_frequent_key_updates: LargeDataDownloadWithFrequentKeyUpdates,
+                    large_data_upload: LargeDataUpload,
+                    large_data_upload_with_frequent_key_updates: LargeDataUploadWithFrequentKeyUpdates,
+                    mtls_request_response: MTLSRequestResponse,
+                    session_resumption: SessionResumption,
+                    hello_retry_request: HelloRetryRequest,
+                    small_tcp_packet: SmallTcpPacket,
+                    key_update_request: KeyUpdateRequest,
+                    key_update_flood: KeyUpdateFlood,
+                    alpn_match: AlpnMatch,
+                    alpn_server_preference: AlpnServerPreference,
+                    alpn_no_overlap: AlpnNoOverlap,
+                    alpn_server_unused: AlpnServerUnused,
+                    sni_selection: SniSelection,
+                    sni_absent: SniAbsent,
+                    untrusted_server_ca: UntrustedServerCa,
+                    hostname_mismatch: HostnameMismatch,
+                    expired_server_cert: ExpiredServerCert,
+                    mtls_missing_client_cert: MTLSMissingClientCert,
+                    mtls_untrusted_client_cert: MTLSUntrustedClientCert,
+                    session_id_resumption: SessionIdResumption,
+                    renegotiation_refusal: RenegotiationRefusal,
+                    pq_handshake: PqHandshake,
+                );
+                interop_tests!(@faults $server, $client,
+                    latency: Latency,
+                    jitter: Jitter,
+                    message_loss: MessageLoss,
+                    partition: Partition,
+                    hold_release: HoldRelease,
+                );
+
+                /// Scenarios that keep a direction of the connection busy,
+                /// with a bounded receive window.
+                mod receive_window {
+                    use super::*;
+
+                    const DEADLOCKS: &[InteropTest] = &[$(InteropTest::$deadlock),*];
+
+                    interop_tests!(@scenarios $server, $client, Some(Fault::ReceiveWindow),
+                        large_data_download: LargeDataDownload,
+                        large_data_download_with_frequent_key_updates: LargeDataDownloadWithFrequentKeyUpdates,
+                        large_data_upload: LargeDataUpload,
+                        large_data_upload_with_frequent_key_updates: LargeDataUploadWithFrequentKeyUpdates,
+                        key_update_flood: KeyUpdateFlood,
+                    );
+                }
+            }
+        )*
+    };
+    (@faults $server:ty, $client:ty, $($module:ident: $fault:ident,)*) => {
+        $(
+            mod $module {
+                use super::*;
+
+                interop_tests!(@scenarios $server, $client, Some(Fault::$fault),
+                    greeting: Greeting,
+                    mtls_request_response: MTLSRequestResponse,
+                    session_resumption: SessionResumption,
+                    hello_retry_request: HelloRetryRequest,
+                );
+            }
+        )*
+    };
+    (@scenarios $server:ty, $client:ty, $fault:expr, $($name:ident: $test:ident,)*) => {
+        $(
+            scenario!($test, #[test] fn $name() -> turmoil::Result {
+                run_scenario::<$server, $client>(
+                    InteropTest::$test, $fault, KNOWN_FAILURES, FLAKY, DEADLOCKS
+                )
+            });
+        )*
+    };
+}
+
+// s2n-tls servers ignore an ALPN mismatch and complete the handshake.
+//
+// s2n-tls servers don't read while they are writing, so the client's KeyUpdates
+// fill the receive window and neither peer can make progress. This is the
+// deadlock that the JDK causes once it exceeds its cipher limit.
+//
+// OpenSSL servers answer every KeyUpdate request that they read while they are
+// waiting for the client to close, and rustls aborts the connection after 32
+// KeyUpdates without any application data in between. This depends on how many
+// requests are still unread when the server finishes writing, so it is flaky,
+// e.g. it fails with `TURMOIL_DATA_VOLUME=4x1 TURMOIL_SEED=0`.
+interop_tests! {
+    s2n_tls_server_s2n_tls_client: S2NShim, S2NShim,
+        known_failures: [AlpnNoOverlap], flaky: [], deadlocks: [],
+        unsupported: [KeyUpdateFlood, KeyUpdateRequest, RenegotiationRefusal, SessionIdResumption];
+    s2n_tls_server_rustls_client: S2NShim, RustlsShim,
+        known_failures: [AlpnNoOverlap], flaky: [], deadlocks: [KeyUpdateFlood],
+        unsupported: [RenegotiationRefusal, SessionIdResumption];
+    openssl_server_s2n_tls_client: OpensslShim, S2NShim,
+        known_failures: [], flaky: [], deadlocks: [],
+        unsupported: [
+            KeyUpdateFlood, KeyUpdateRequest, PqHandshake, RenegotiationRefusal, SessionIdResumption
+        ];
+    openssl_server_rustls_client: OpensslShim, RustlsShim,
+        known_failures: [], flaky: [KeyUpdateFlood], deadlocks: [],
+        unsupported: [PqHandshake, RenegotiationRefusal];
 }
 
+/// A peer that reads the client's first flight, but never responds, must fail
+/// the scenario at the deadline, even under a fault that the peers can't
+/// recover from.
 #[test]
-fn turmoil_interop() -> turmoil::Result {
-    tracing_subscriber::fmt::fmt()
-        .with_max_level(Level::INFO)
-        .init();
-    for i in 0..100 {
-        let rand = Box::new(rand::rngs::SmallRng::seed_from_u64(7));
-        let mut sim = turmoil::Builder::new().build_with_rng(rand);
-    
-        for t in TEST_CASES {
-            setup_scenario::<S2NShim, RustlsShim>(&mut sim, t);
-            //setup_scenario::<S2NShim, S2NShim>(&mut sim, t);
-            //setup_scenario::<OpensslShim, RustlsShim>(&mut sim, t);
-            //setup_scenario::<OpensslShim, S2NShim>(&mut sim, t);
+fn hung_peer_fails_under_partition() {
+    let test = InteropTest::Greeting;
+    let fault = Some(Fault::Partition);
+    let mut sim = turmoil::Builder::new()
+        .simulation_duration(SIMULATION_DURATION)
+        .tick_duration(TICK_DURATION)
+        .build();
+    let flow_control = Fault::flow_control(fault);
+    let server_flow_control = flow_control.clone();
+    sim.host("server", move || {
+        let flow_control = server_flow_control.clone();
+        async move {
+            let listener =
+                turmoil::net::TcpListener::bind(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, PORT))
+                    .await?;
+            let (stream, _peer_addr) = listener.accept().await?;
+            let mut stream = flow_control.server(stream);
+            let mut buffer = [0; 1024];
+            while stream.read(&mut buffer).await? > 0 {}
+            Ok(())
         }
-    
-        sim.run().unwrap();
-    }
-    Ok(())
+    });
+    let config = <RustlsShim as ClientTLS<Transport>>::get_client_config(test, parameters(test))
+        .unwrap()
+        .unwrap();
+    let connector = <RustlsShim as ClientTLS<Transport>>::connector(config);
+    sim.client(
+        "client",
+        client_loop::<RustlsShim>(test, connector, flow_control, fault),
+    );
+
+    let error = sim.run().unwrap_err();
+    assert!(error.is::<DeadlineExceeded>(), "{error}");
 }